@@ -0,0 +1,5 @@
+DROP TABLE awards;
+DROP TABLE alliance_teams;
+DROP TABLE rankings;
+DROP TABLE district_teams;
+DROP TABLE districts;
@@ -0,0 +1,42 @@
+CREATE TABLE districts (
+       id TEXT PRIMARY KEY NOT NULL,
+       abbreviation TEXT NOT NULL,
+       name TEXT NOT NULL,
+       year INTEGER NOT NULL
+);
+
+CREATE TABLE district_teams (
+       id TEXT PRIMARY KEY NOT NULL,
+       district_id TEXT NOT NULL,
+       team_key TEXT NOT NULL,
+       FOREIGN KEY(district_id) REFERENCES districts(id)
+);
+
+CREATE TABLE rankings (
+       id TEXT PRIMARY KEY NOT NULL,
+       event_id TEXT NOT NULL,
+       team_key TEXT NOT NULL,
+       rank INTEGER NOT NULL,
+       wins INTEGER NOT NULL,
+       losses INTEGER NOT NULL,
+       ties INTEGER NOT NULL,
+       matches_played INTEGER NOT NULL,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+CREATE TABLE alliance_teams (
+       id TEXT PRIMARY KEY NOT NULL,
+       event_id TEXT NOT NULL,
+       alliance INTEGER NOT NULL,
+       pick INTEGER NOT NULL,
+       team_key TEXT NOT NULL,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+CREATE TABLE awards (
+       id TEXT PRIMARY KEY NOT NULL,
+       event_id TEXT NOT NULL,
+       award_type INTEGER NOT NULL,
+       team_key TEXT NOT NULL,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE checkpoints (
+       id INTEGER PRIMARY KEY NOT NULL,
+       parameters TEXT NOT NULL,
+       match_count INTEGER NOT NULL,
+       fingerprint TEXT NOT NULL,
+       ratings TEXT NOT NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE sync_state (
+       url TEXT PRIMARY KEY NOT NULL,
+       last_modified TEXT NOT NULL,
+       etag TEXT NOT NULL
+);
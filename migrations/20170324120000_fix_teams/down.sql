@@ -0,0 +1,6 @@
+DROP TABLE teams;
+CREATE TABLE teams (
+       team_numer PRIMARY KEY,
+       nickname TEXT,
+       key TEXT
+);
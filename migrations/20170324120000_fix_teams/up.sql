@@ -0,0 +1,10 @@
+DROP TABLE teams;
+CREATE TABLE teams (
+       key TEXT PRIMARY KEY NOT NULL,
+       team_number INTEGER NOT NULL,
+       nickname TEXT,
+       city TEXT,
+       state_prov TEXT,
+       country TEXT,
+       rookie_year INTEGER
+);
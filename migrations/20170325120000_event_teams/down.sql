@@ -0,0 +1,21 @@
+DROP TABLE event_teams;
+
+ALTER TABLE rankings RENAME TO temp_rankings;
+
+CREATE TABLE rankings (
+       id TEXT PRIMARY KEY NOT NULL,
+       event_id TEXT NOT NULL,
+       team_key TEXT NOT NULL,
+       rank INTEGER NOT NULL,
+       wins INTEGER NOT NULL,
+       losses INTEGER NOT NULL,
+       ties INTEGER NOT NULL,
+       matches_played INTEGER NOT NULL,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+INSERT INTO rankings
+SELECT id, event_id, team_key, rank, wins, losses, ties, matches_played
+FROM temp_rankings;
+
+DROP TABLE temp_rankings;
@@ -0,0 +1,9 @@
+CREATE TABLE event_teams (
+       id TEXT PRIMARY KEY NOT NULL,
+       event_id TEXT NOT NULL,
+       team_key TEXT NOT NULL,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+ALTER TABLE rankings ADD COLUMN ranking_score INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE rankings ADD COLUMN tiebreaker DOUBLE NOT NULL DEFAULT 0;
@@ -0,0 +1,25 @@
+ALTER TABLE matches RENAME TO temp_matches;
+
+CREATE TABLE matches (
+       id TEXT PRIMARY KEY NOT NULL,
+       comp_level TEXT NOT NULL,
+       match_number INTEGER NOT NULL,
+       set_number INTEGER NOT NULL,
+       event_id TEXT NOT NULL,
+       red_score INTEGER NOT NULL,
+       blue_score INTEGER NOT NULL,
+       red1 TEXT NOT NULL,
+       red2 TEXT NOT NULL,
+       red3 TEXT,
+       blue1 TEXT NOT NULL,
+       blue2 TEXT NOT NULL,
+       blue3 TEXT,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+INSERT INTO matches
+SELECT id, comp_level, match_number, set_number, event_id, red_score, blue_score,
+       red1, red2, red3, blue1, blue2, blue3
+FROM temp_matches;
+
+DROP TABLE temp_matches;
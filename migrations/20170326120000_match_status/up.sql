@@ -0,0 +1,5 @@
+ALTER TABLE matches ADD COLUMN status TEXT NOT NULL DEFAULT 'scheduled';
+ALTER TABLE matches ADD COLUMN time BIGINT;
+ALTER TABLE matches ADD COLUMN actual_time BIGINT;
+
+UPDATE matches SET status = 'played' WHERE red_score > -1 AND blue_score > -1;
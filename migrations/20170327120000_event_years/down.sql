@@ -0,0 +1,17 @@
+ALTER TABLE events RENAME TO temp_events;
+
+CREATE TABLE events (
+       id TEXT PRIMARY KEY NOT NULL,
+       name TEXT NOT NULL,
+       event_type INTEGER NOT NULL,
+       official INTEGER NOT NULL,
+       start_date TEXT NOT NULL,
+       week INTEGER NOT NULL DEFAULT 8,
+       district TEXT
+);
+
+INSERT INTO events
+SELECT id, name, event_type, official, start_date, week, district
+FROM temp_events;
+
+DROP TABLE temp_events;
@@ -0,0 +1,3 @@
+ALTER TABLE events ADD COLUMN year INTEGER NOT NULL DEFAULT 0;
+
+UPDATE events SET year = CAST(substr(start_date, 1, 4) AS INTEGER);
@@ -0,0 +1,50 @@
+ALTER TABLE matches RENAME TO temp_matches;
+
+CREATE TABLE matches (
+       id TEXT PRIMARY KEY NOT NULL,
+       comp_level TEXT NOT NULL,
+       match_number INTEGER NOT NULL,
+       set_number INTEGER NOT NULL,
+       event_id TEXT NOT NULL,
+       red_score INTEGER NOT NULL,
+       blue_score INTEGER NOT NULL,
+       red1 TEXT NOT NULL,
+       red2 TEXT NOT NULL,
+       red3 TEXT,
+       blue1 TEXT NOT NULL,
+       blue2 TEXT NOT NULL,
+       blue3 TEXT,
+       status TEXT NOT NULL DEFAULT 'scheduled',
+       time BIGINT,
+       actual_time BIGINT,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+-- Only the first three teams of each alliance fit. Matches with
+-- an alliance of a single team are dropped.
+INSERT INTO matches
+SELECT id, comp_level, match_number, set_number, event_id, red_score, blue_score,
+       substr(red, 1, instr(red, ' ') - 1),
+       substr(red_rest, 1, instr(red_rest, ' ') - 1),
+       NULLIF(substr(red_last, 1, instr(red_last, ' ') - 1), ''),
+       substr(blue, 1, instr(blue, ' ') - 1),
+       substr(blue_rest, 1, instr(blue_rest, ' ') - 1),
+       NULLIF(substr(blue_last, 1, instr(blue_last, ' ') - 1), ''),
+       status, time, actual_time
+FROM (
+     SELECT *,
+            substr(red_rest, instr(red_rest, ' ') + 1) AS red_last,
+            substr(blue_rest, instr(blue_rest, ' ') + 1) AS blue_last
+     FROM (
+          SELECT *,
+                 substr(red, instr(red, ' ') + 1) AS red_rest,
+                 substr(blue, instr(blue, ' ') + 1) AS blue_rest
+          FROM (
+               SELECT *, red_teams || ' ' AS red, blue_teams || ' ' AS blue
+               FROM temp_matches
+          )
+     )
+)
+WHERE instr(red_teams, ' ') > 0 AND instr(blue_teams, ' ') > 0;
+
+DROP TABLE temp_matches;
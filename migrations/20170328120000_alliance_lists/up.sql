@@ -0,0 +1,26 @@
+ALTER TABLE matches RENAME TO temp_matches;
+
+CREATE TABLE matches (
+       id TEXT PRIMARY KEY NOT NULL,
+       comp_level TEXT NOT NULL,
+       match_number INTEGER NOT NULL,
+       set_number INTEGER NOT NULL,
+       event_id TEXT NOT NULL,
+       red_score INTEGER NOT NULL,
+       blue_score INTEGER NOT NULL,
+       red_teams TEXT NOT NULL,
+       blue_teams TEXT NOT NULL,
+       status TEXT NOT NULL DEFAULT 'scheduled',
+       time BIGINT,
+       actual_time BIGINT,
+       FOREIGN KEY(event_id) REFERENCES events(id)
+);
+
+INSERT INTO matches
+SELECT id, comp_level, match_number, set_number, event_id, red_score, blue_score,
+       red1 || ' ' || red2 || COALESCE(' ' || red3, ''),
+       blue1 || ' ' || blue2 || COALESCE(' ' || blue3, ''),
+       status, time, actual_time
+FROM temp_matches;
+
+DROP TABLE temp_matches;
@@ -0,0 +1 @@
+UPDATE events SET official = 1 WHERE event_type != 99;
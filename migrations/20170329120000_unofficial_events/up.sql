@@ -0,0 +1,2 @@
+-- Preseason and other events outside the competition season are unofficial.
+UPDATE events SET official = 0 WHERE event_type NOT BETWEEN 0 AND 5;
@@ -0,0 +1,100 @@
+use super::elo::{Teams, Rookie, Weights};
+use super::models::{Event, Matche};
+use super::{get_matches, rating_policy, rating_weights, with_seasons, FIRST_YEAR};
+use error::Result;
+
+/// Rookie ratings compared against the configured policy.
+const ROOKIE_POLICIES: &'static [Rookie] = &[Rookie::Fixed(0f64), Rookie::Fixed(75f64),
+    Rookie::Fixed(150f64), Rookie::Percentile(10f64), Rookie::Percentile(25f64),
+    Rookie::Percentile(50f64)];
+/// Inactivity decays compared against the configured policy.
+const DECAYS: &'static [f64] = &[0f64, 0.1f64, 0.25f64, 0.5f64];
+/// Weights compared against the default, written as for `ELO_WEIGHTS`.
+const WEIGHTS: &'static [&'static str] = &["dcmp=1.25 cmp=1.5 einstein=1.5",
+    "dcmp=0.75 cmp=0.75 einstein=0.75", "week=-0.05", "week=0.05", "qf=0.5 sf=0.5 f=0.5",
+    "qf=1 sf=1 f=1", "ef=0.2 qf=0.2 sf=0.2 f=0.2"];
+
+/// How well one set of ratings predicted the current season.
+pub struct Outcome {
+    /// What was changed to get these ratings.
+    pub label: String,
+    pub brier: f64,
+    /// The share of matches whose winner was predicted.
+    pub accuracy: f64,
+    pub matches: usize,
+    /// Whether this is what is set in the environment.
+    pub configured: bool,
+}
+
+/// Rate every official match with the given policy and weights.
+fn score(event_list: &[Event], match_list: &[Matche], k: f64, carry_over: f64,
+         rookie: Rookie, decay: f64, weights: &Weights) -> (f64, f64, usize) {
+    let mut team_list = Teams::new(k, carry_over, FIRST_YEAR as usize);
+    team_list.set_policy(rookie, decay);
+    team_list.set_weights(weights.clone());
+    team_list.replay(with_seasons(event_list, match_list, 0f64, weights));
+    return (team_list.brier / team_list.total as f64,
+            team_list.wins_correct as f64 / team_list.total as f64,
+            team_list.total);
+}
+
+/// Rate every match once for each rookie policy and once for each
+/// inactivity decay, keeping the other half of the configured policy.
+/// The configured policy comes first.
+pub fn rookie_policies(k: f64, carry_over: f64) -> Result<Vec<Outcome>> {
+    let (event_list, match_list) = get_matches(false)?;
+    let (rookie, decay) = rating_policy()?;
+    let weights = rating_weights()?;
+    let mut policies = vec![(rookie, decay)];
+    for &r in ROOKIE_POLICIES {
+        policies.push((r, decay));
+    }
+    for &d in DECAYS {
+        policies.push((rookie, d));
+    }
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    for (r, d) in policies {
+        let label = format!("rookie={} decay={}", r, d);
+        if outcomes.iter().any(|o| o.label == label) {
+            continue;
+        }
+        let (brier, accuracy, matches) = score(&event_list, &match_list, k, carry_over,
+                                               r, d, &weights);
+        outcomes.push(Outcome {
+            label: label,
+            brier: brier,
+            accuracy: accuracy,
+            matches: matches,
+            configured: outcomes.len() == 0,
+        });
+    }
+    return Ok(outcomes);
+}
+
+/// Rate every match with the default weights, then with the configured
+/// weights and each of the alternatives. The default comes first.
+pub fn event_weights(k: f64, carry_over: f64) -> Result<Vec<Outcome>> {
+    let (event_list, match_list) = get_matches(false)?;
+    let (rookie, decay) = rating_policy()?;
+    let configured = rating_weights()?;
+    let mut candidates = vec![(String::from("default"), Weights::default())];
+    if configured != Weights::default() {
+        candidates.push((String::from("ELO_WEIGHTS"), configured.clone()));
+    }
+    for w in WEIGHTS {
+        candidates.push((w.to_string(), Weights::parse(w).unwrap()));
+    }
+    let mut outcomes = Vec::new();
+    for (label, weights) in candidates {
+        let (brier, accuracy, matches) = score(&event_list, &match_list, k, carry_over,
+                                               rookie, decay, &weights);
+        outcomes.push(Outcome {
+            label: label,
+            brier: brier,
+            accuracy: accuracy,
+            matches: matches,
+            configured: weights == configured,
+        });
+    }
+    return Ok(outcomes);
+}
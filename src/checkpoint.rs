@@ -0,0 +1,111 @@
+use super::models::{Matche, Checkpoint, NewCheckpoint};
+use super::elo::Teams;
+use diesel;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use serde_json;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use error::Result;
+
+/// The checkpoint of ratings from official events only.
+pub const OFFICIAL: i32 = 1;
+/// The checkpoint of ratings which also include off-season events.
+pub const WITH_OFFSEASON: i32 = 2;
+
+/// Ratings saved after processing the first `match_count` matches.
+pub struct SavedRatings {
+    pub teams: Teams,
+    pub match_count: usize,
+    pub fingerprint: String,
+}
+
+/// Fingerprint the first `count` matches in processing order, each
+/// with the season and weight it is rated with.
+///
+/// Any change to the order, the results, the alliances, the season or
+/// weight of their events or the set of matches before the checkpoint
+/// produces a different fingerprint.
+/// Returns `None` if there are fewer than `count` matches.
+pub fn fingerprint(match_list: &[(usize, f64, &Matche)], count: usize) -> Option<String> {
+    let mut hasher = DefaultHasher::new();
+    let mut seen = 0;
+    for &(season, weight, m) in match_list.iter().take(count) {
+        m.id.hash(&mut hasher);
+        m.red_score.hash(&mut hasher);
+        m.blue_score.hash(&mut hasher);
+        m.red_teams.hash(&mut hasher);
+        m.blue_teams.hash(&mut hasher);
+        season.hash(&mut hasher);
+        weight.to_bits().hash(&mut hasher);
+        seen += 1;
+    }
+    if seen < count {
+        return None;
+    }
+    return Some(format!("{:016x}", hasher.finish()));
+}
+
+/// Load the ratings saved in `slot` if they were computed with `params`.
+pub fn load(conn: &SqliteConnection, slot: i32, params: &str) -> Result<Option<SavedRatings>> {
+    use schema::checkpoints::dsl::*;
+    let saved = match checkpoints.find(slot)
+        .select((parameters, match_count, fingerprint, ratings))
+        .first::<Checkpoint>(conn) {
+        Ok(c) => c,
+        Err(diesel::result::Error::NotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if saved.parameters != params {
+        return Ok(None);
+    }
+    // Ratings saved by an older version are simply recomputed.
+    let teams: Teams = match serde_json::from_str(&saved.ratings) {
+        Ok(t) => t,
+        Err(_) => return Ok(None),
+    };
+    return Ok(Some(SavedRatings {
+        teams: teams,
+        match_count: saved.match_count as usize,
+        fingerprint: saved.fingerprint,
+    }));
+}
+
+/// Replace the ratings saved in `slot`.
+pub fn save(conn: &SqliteConnection, slot: i32, params: &str, teams: &Teams, count: usize,
+            print: &str) -> Result<()> {
+    use schema::checkpoints::dsl::*;
+    let data = serde_json::to_string(teams)?;
+    let checkpoint = NewCheckpoint {
+        id: slot,
+        parameters: params,
+        match_count: count as i32,
+        fingerprint: print,
+        ratings: &data,
+    };
+    diesel::insert_or_replace(&checkpoint).into(checkpoints).execute(conn)?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::played;
+
+    fn red_win() -> Matche {
+        return played("2017ca", 1, &["frc1", "frc2", "frc3"], &["frc4", "frc5", "frc6"], 100, 50);
+    }
+
+    #[test]
+    fn fingerprint_changes_with_alliances_seasons_and_weights() {
+        let m = red_win();
+        let mut moved = red_win();
+        moved.blue_teams = String::from("frc4 frc5 frc7");
+        let print = fingerprint(&[(2017, 1f64, &m)], 1);
+        assert_eq!(print, fingerprint(&[(2017, 1f64, &red_win())], 1));
+        assert!(print != fingerprint(&[(2017, 1f64, &moved)], 1));
+        assert!(print != fingerprint(&[(2016, 1f64, &m)], 1));
+        assert!(print != fingerprint(&[(2017, 0.5f64, &m)], 1));
+        assert_eq!(fingerprint(&[(2017, 1f64, &m)], 2), None);
+    }
+}
@@ -0,0 +1,422 @@
+use curl::easy::{Easy, List};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, Condvar};
+use std::thread;
+use std::time::{Duration, Instant};
+use error::{Error, Result};
+use serde_json;
+
+/// The Blue Alliance API used unless `TBA_URL` is set.
+const API_URL: &'static str = "https://www.thebluealliance.com/api/v3";
+/// Requests in flight at once unless `TBA_CONCURRENCY` is set.
+const CONCURRENCY: usize = 8;
+/// Requests started per second unless `TBA_RATE_LIMIT` is set.
+const RATE_LIMIT: f64 = 20f64;
+/// Attempts made for each request before giving up.
+const ATTEMPTS: u32 = 4;
+/// Wait before the first retry. Doubles with every attempt.
+const BACKOFF_MS: u64 = 500;
+/// Requests taking longer than this are treated as failed.
+const TIMEOUT_SECS: u64 = 30;
+
+/// Where responses come from and whether they are kept.
+pub enum Mode {
+    /// Request everything from the API.
+    Live,
+    /// Request from the API and save every response under a directory.
+    /// Each URL's responses are kept in `<url>.recorded.json`, apart from
+    /// the bare responses a `FixtureSource` reads from `<url>.json`.
+    Record(PathBuf),
+    /// Serve the responses saved by an earlier `Record` run.
+    Replay(PathBuf),
+}
+
+/// What was last seen of a resource, used to only request changes.
+#[derive(Clone, Debug, Default)]
+pub struct Stamp {
+    pub last_modified: String,
+    pub etag: String,
+}
+
+impl Stamp {
+    /// Whether `response` is the version of the resource this was taken from.
+    fn matches(&self, response: &Response) -> bool {
+        return (self.etag.len() > 0 && self.etag == response.etag) ||
+            (self.last_modified.len() > 0 && self.last_modified.trim() == response.last_modified.trim());
+    }
+}
+
+#[derive(Clone)]
+pub struct Response {
+    pub code: u32,
+    pub data: Vec<u8>,
+    pub last_modified: String,
+    pub etag: String,
+}
+
+/// A response as saved to disk while recording.
+#[derive(Serialize, Deserialize, Clone)]
+struct Recorded {
+    code: u32,
+    last_modified: String,
+    #[serde(default)]
+    etag: String,
+    data: String,
+}
+
+/// A client for The Blue Alliance shared by every thread.
+///
+/// Limits how many requests are in flight and how quickly new ones
+/// are started, retries server errors and timeouts, and reuses
+/// responses for as long as their `Cache-Control` header allows.
+pub struct Client {
+    /// Stamps of earlier responses keyed by URL.
+    pub history: Mutex<HashMap<String, Stamp>>,
+    base_url: String,
+    auth_key: String,
+    concurrency: usize,
+    in_flight: Mutex<usize>,
+    finished: Condvar,
+    interval: Duration,
+    next_start: Mutex<Instant>,
+    cache: Mutex<HashMap<String, (Instant, Response)>>,
+    mode: Mode,
+    /// Responses recorded, or replayed so far, keyed by URL.
+    recordings: Mutex<HashMap<String, Vec<Recorded>>>,
+}
+
+/// Releases a request slot when dropped.
+struct Slot<'a> {
+    client: &'a Client,
+}
+
+impl<'a> Drop for Slot<'a> {
+    fn drop(&mut self) {
+        let mut in_flight = self.client.in_flight.lock().expect("Could not lock request slots");
+        *in_flight -= 1;
+        self.client.finished.notify_one();
+    }
+}
+
+impl Client {
+    /// Create a client configured from the environment.
+    pub fn new(history: HashMap<String, Stamp>, mode: Mode) -> Result<Client> {
+        let auth_key = match (env::var("TBA_KEY"), &mode) {
+            (Ok(key), _) => key,
+            (Err(_), &Mode::Replay(_)) => String::new(),
+            (Err(_), _) => return Err(Error::Config(String::from("TBA_KEY must be set"))),
+        };
+        let concurrency = match env::var("TBA_CONCURRENCY") {
+            Ok(c) => match c.parse::<usize>() {
+                Ok(c) if c > 0 => c,
+                _ => return Err(Error::Config(format!("Invalid TBA_CONCURRENCY: {}", c))),
+            },
+            Err(_) => CONCURRENCY,
+        };
+        let rate = match env::var("TBA_RATE_LIMIT") {
+            Ok(r) => match r.parse::<f64>() {
+                Ok(r) if r > 0f64 => r,
+                _ => return Err(Error::Config(format!("Invalid TBA_RATE_LIMIT: {}", r))),
+            },
+            Err(_) => RATE_LIMIT,
+        };
+        let nanos = (1e9f64 / rate) as u64;
+        return Ok(Client {
+            history: Mutex::new(history),
+            base_url: env::var("TBA_URL").unwrap_or(String::from(API_URL)),
+            auth_key: auth_key,
+            concurrency: concurrency,
+            in_flight: Mutex::new(0),
+            finished: Condvar::new(),
+            interval: Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32),
+            next_start: Mutex::new(Instant::now()),
+            cache: Mutex::new(HashMap::new()),
+            mode: mode,
+            recordings: Mutex::new(HashMap::new()),
+        });
+    }
+
+    /// Request `url_ext`, only returning data if it has changed
+    /// since `stamp` was taken when one is given.
+    pub fn request(&self, url_ext: &str, stamp: Option<&Stamp>) -> Result<Response> {
+        if let Mode::Replay(ref dir) = self.mode {
+            return self.replay(dir, url_ext);
+        }
+        if let Some(cached) = self.cached(url_ext) {
+            if stamp.map_or(false, |s| s.matches(&cached)) {
+                return Ok(Response {
+                    code: 304,
+                    data: Vec::new(),
+                    last_modified: cached.last_modified,
+                    etag: cached.etag,
+                });
+            }
+            return Ok(cached);
+        }
+        let mut attempt = 0;
+        loop {
+            let outcome = {
+                let _slot = self.acquire();
+                self.wait_turn();
+                self.perform(url_ext, stamp)
+            };
+            attempt += 1;
+            let retry = match outcome {
+                Ok((ref response, _)) => response.code >= 500,
+                Err(Error::Network(ref e)) => e.is_operation_timedout(),
+                Err(_) => false,
+            };
+            if !retry || attempt >= ATTEMPTS {
+                let (response, max_age) = outcome?;
+                if response.code == 200 {
+                    if let Some(age) = max_age {
+                        let mut cache = self.cache.lock().expect("Could not lock cache");
+                        cache.insert(url_ext.to_owned(),
+                                     (Instant::now() + Duration::from_secs(age), response.clone()));
+                    }
+                }
+                if let Mode::Record(ref dir) = self.mode {
+                    self.record(dir, url_ext, &response)?;
+                }
+                return Ok(response);
+            }
+            thread::sleep(Duration::from_millis(BACKOFF_MS << (attempt - 1)));
+        }
+    }
+
+    /// Save `response` after any earlier responses for `url_ext`.
+    ///
+    /// Every response for a URL is kept, in order, in
+    /// `<dir>/<url_ext>.recorded.json` so repeated polling can be replayed.
+    fn record(&self, dir: &PathBuf, url_ext: &str, response: &Response) -> Result<()> {
+        let mut recordings = self.recordings.lock().expect("Could not lock recordings");
+        let list = recordings.entry(url_ext.to_owned()).or_insert(Vec::new());
+        list.push(Recorded {
+            code: response.code,
+            last_modified: response.last_modified.clone(),
+            etag: response.etag.clone(),
+            data: String::from_utf8_lossy(&response.data).into_owned(),
+        });
+        let path = recording(dir, url_ext);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path)?;
+        file.write_all(serde_json::to_string_pretty(list)?.as_bytes())?;
+        return Ok(());
+    }
+
+    /// Serve the next recorded response for `url_ext`. Once every
+    /// recorded response has been served the last one is repeated.
+    fn replay(&self, dir: &PathBuf, url_ext: &str) -> Result<Response> {
+        let mut recordings = self.recordings.lock().expect("Could not lock recordings");
+        if !recordings.contains_key(url_ext) {
+            let path = recording(dir, url_ext);
+            let mut file = match File::open(&path) {
+                Ok(f) => f,
+                Err(ref e) if e.kind() == ErrorKind::NotFound => {
+                    return Err(Error::Config(format!("No recorded response for {}", url_ext)));
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let mut data = String::new();
+            file.read_to_string(&mut data)?;
+            let mut list: Vec<Recorded> = serde_json::from_str(&data)?;
+            list.reverse();
+            recordings.insert(url_ext.to_owned(), list);
+        }
+        let list = recordings.get_mut(url_ext).expect("Recorded responses");
+        let recorded = if list.len() > 1 {
+            list.pop().expect("Recorded response")
+        } else {
+            match list.last() {
+                Some(r) => r.clone(),
+                None => return Err(Error::Config(format!("No recorded response for {}", url_ext))),
+            }
+        };
+        return Ok(Response {
+            code: recorded.code,
+            data: recorded.data.into_bytes(),
+            last_modified: recorded.last_modified,
+            etag: recorded.etag,
+        });
+    }
+
+    /// A stored response which has not yet expired.
+    fn cached(&self, url_ext: &str) -> Option<Response> {
+        let cache = self.cache.lock().expect("Could not lock cache");
+        match cache.get(url_ext) {
+            Some(&(expires, ref response)) if expires > Instant::now() => Some(response.clone()),
+            _ => None,
+        }
+    }
+
+    /// Block until fewer than `concurrency` requests are in flight.
+    fn acquire(&self) -> Slot {
+        let mut in_flight = self.in_flight.lock().expect("Could not lock request slots");
+        while *in_flight >= self.concurrency {
+            in_flight = self.finished.wait(in_flight).expect("Could not wait for request slot");
+        }
+        *in_flight += 1;
+        return Slot { client: self };
+    }
+
+    /// Block until the rate limit allows another request to start.
+    fn wait_turn(&self) {
+        let mut next_start = self.next_start.lock().expect("Could not lock rate limit");
+        let now = Instant::now();
+        if *next_start > now {
+            thread::sleep(*next_start - now);
+            *next_start += self.interval;
+        } else {
+            *next_start = now + self.interval;
+        }
+    }
+
+    /// Make a single request, returning the response along with
+    /// the `max-age` given by its `Cache-Control` header.
+    fn perform(&self, url_ext: &str, stamp: Option<&Stamp>) -> Result<(Response, Option<u64>)> {
+        let request_url = format!("{}/{}", self.base_url.trim_end_matches('/'), url_ext);
+        let mut easy = Easy::new();
+        let mut list = List::new();
+        let mut data = Vec::new();
+        list.append("X-TBA-App-Id: Carl Colglazier:FRC ELO:0.0.0")?;
+        list.append(&format!("X-TBA-Auth-Key: {}", self.auth_key))?;
+        if let Some(stamp) = stamp {
+            if stamp.last_modified.len() > 0 {
+                list.append(&format!("If-Modified-Since: {}", stamp.last_modified))?;
+            }
+            if stamp.etag.len() > 0 {
+                list.append(&format!("If-None-Match: {}", stamp.etag))?;
+            }
+        }
+        easy.http_headers(list)?;
+        easy.url(&request_url)?;
+        easy.timeout(Duration::from_secs(TIMEOUT_SECS))?;
+        let mut headers = String::new();
+        let mut etag = String::new();
+        let mut max_age = None;
+        {
+            let mut transfer = easy.transfer();
+            transfer.write_function(|new_data| {
+                data.extend_from_slice(new_data);
+                Ok(new_data.len())
+            })?;
+            transfer.header_function(|header| {
+                let s = String::from_utf8_lossy(header);
+                let lower = s.to_lowercase();
+                if lower.starts_with("last-modified:") {
+                    headers.push_str(s[14..].trim());
+                } else if lower.starts_with("etag:") {
+                    etag.push_str(s[5..].trim());
+                } else if lower.starts_with("cache-control:") {
+                    max_age = parse_max_age(&lower[14..]);
+                }
+                true
+            })?;
+            transfer.perform()?;
+        }
+        let code = easy.response_code()?;
+        return Ok((Response {
+            code: code,
+            data: data,
+            last_modified: headers,
+            etag: etag,
+        }, max_age));
+    }
+}
+
+/// Where the responses for `url_ext` are recorded under `dir`.
+fn recording(dir: &PathBuf, url_ext: &str) -> PathBuf {
+    return dir.join(format!("{}.recorded.json", url_ext));
+}
+
+/// Read `max-age` from the value of a `Cache-Control` header.
+fn parse_max_age(value: &str) -> Option<u64> {
+    for directive in value.split(',') {
+        let directive = directive.trim();
+        if directive.starts_with("max-age=") {
+            return directive[8..].parse().ok();
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{lock_environment, not_modified, ok, stand_in};
+
+    #[test]
+    fn max_age_is_read_from_cache_control() {
+        assert_eq!(parse_max_age(" max-age=60"), Some(60));
+        assert_eq!(parse_max_age(" public, max-age=61"), Some(61));
+        assert_eq!(parse_max_age(" max-age=0"), Some(0));
+        assert_eq!(parse_max_age(" no-cache"), None);
+        assert_eq!(parse_max_age(""), None);
+        assert_eq!(parse_max_age(" max-age=soon"), None);
+        assert_eq!(parse_max_age(" max-age=-1"), None);
+        assert_eq!(parse_max_age(" max-age="), None);
+    }
+
+    #[test]
+    fn responses_are_reused_until_they_expire() {
+        let _environment = lock_environment();
+        let (url, requests) = stand_in(vec![
+            ok(&["Cache-Control: public, max-age=60"], "[2017]"),
+            ok(&["Cache-Control: max-age=0"], "[2016]"),
+            ok(&["Cache-Control: max-age=0"], "[2016]"),
+        ]);
+        env::set_var("TBA_URL", &url);
+        env::set_var("TBA_KEY", "test");
+        let client = Client::new(HashMap::new(), Mode::Live);
+        env::remove_var("TBA_URL");
+        env::remove_var("TBA_KEY");
+        let client = client.unwrap();
+
+        for _ in 0..2 {
+            let response = client.request("events/2017", None).unwrap();
+            assert_eq!((response.code, response.data), (200, b"[2017]".to_vec()));
+        }
+        for _ in 0..2 {
+            assert_eq!(client.request("events/2016", None).unwrap().data, b"[2016]".to_vec());
+        }
+        let requests = requests.join().unwrap();
+        // The first response was served from the cache the second time.
+        assert_eq!(requests.len(), 3);
+        assert!(requests[1].starts_with("GET /api/v3/events/2016 "));
+    }
+
+    #[test]
+    fn recorded_responses_are_replayed_in_order() {
+        let _environment = lock_environment();
+        let dir = env::temp_dir().join(format!("frc-elo-recorded-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let modified = "Last-Modified: Wed, 01 Mar 2017 12:00:00 GMT";
+        let (url, requests) = stand_in(vec![ok(&[modified], "[]"), not_modified()]);
+        env::set_var("TBA_URL", &url);
+        env::set_var("TBA_KEY", "test");
+        let recorder = Client::new(HashMap::new(), Mode::Record(dir.clone()));
+        env::remove_var("TBA_URL");
+        env::remove_var("TBA_KEY");
+        let recorder = recorder.unwrap();
+        recorder.request("events/2017", None).unwrap();
+        recorder.request("events/2017", None).unwrap();
+        requests.join().unwrap();
+        // Recordings do not take the place of a fixture for the URL.
+        assert!(dir.join("events/2017.recorded.json").exists());
+        assert!(!dir.join("events/2017.json").exists());
+
+        let replayer = Client::new(HashMap::new(), Mode::Replay(dir)).unwrap();
+        let first = replayer.request("events/2017", None).unwrap();
+        assert_eq!((first.code, first.last_modified.as_str()),
+                   (200, "Wed, 01 Mar 2017 12:00:00 GMT"));
+        for _ in 0..2 {
+            assert_eq!(replayer.request("events/2017", None).unwrap().code, 304);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use super::models::Matche;
+use super::elo::Teams;
+use super::history::{season_rating, SeasonRating};
+use super::{get_matches, replay_matches};
+use error::Result;
+
+/// A match both teams played in.
+pub struct Meeting {
+    pub played: Matche,
+    /// Whether the teams were on the same alliance.
+    pub partners: bool,
+    /// The chance the first team's alliance was given of winning.
+    pub chance: f64,
+    /// 1 if the first team's alliance won, 0 if it lost and 0.5 for a tie.
+    pub result: f64,
+}
+
+/// Everything the two teams have done together or against each other.
+pub struct Comparison {
+    pub meetings: Vec<Meeting>,
+    /// Each team's rating at the end of every season, if it played.
+    pub history: Vec<(usize, Option<SeasonRating>, Option<SeasonRating>)>,
+}
+
+/// Replay every match, noting each one the two teams played in and
+/// their ratings as each season ends.
+pub fn compare(first: &str, second: &str, offseason: Option<f64>) -> Result<Comparison> {
+    let (event_list, match_list) = get_matches(offseason.is_some())?;
+    let (first, second) = (first.to_owned(), second.to_owned());
+    let mut meetings = Vec::new();
+    let mut history = Vec::new();
+    let mut current = None;
+    let mut seen = (false, false);
+    let mut team_list = replay_matches(&event_list, &match_list, offseason,
+                                       |team_list, season, m| {
+        if current.map(|c| c < season).unwrap_or(false) {
+            history.push(season_end(team_list, current.unwrap(), seen, &first, &second));
+            seen = (false, false);
+        }
+        current = Some(season);
+        team_list.advance_to(season);
+        let (red, blue) = (m.get_red(), m.get_blue());
+        let side = |team: &String| if red.contains(team) {
+            Some(true)
+        } else if blue.contains(team) {
+            Some(false)
+        } else {
+            None
+        };
+        let (first_side, second_side) = (side(&first), side(&second));
+        seen = (seen.0 || first_side.is_some(), seen.1 || second_side.is_some());
+        if let (Some(first_red), Some(second_red)) = (first_side, second_side) {
+            let p = team_list.predict(m);
+            meetings.push(Meeting {
+                played: m.clone(),
+                partners: first_red == second_red,
+                chance: if first_red { p } else { 1f64 - p },
+                result: if first_red { m.actual_r() } else { m.actual_b() },
+            });
+        }
+    })?;
+    if let Some(season) = current {
+        history.push(season_end(&mut team_list, season, seen, &first, &second));
+    }
+    history.retain(|&(_, ref a, ref b)| a.is_some() || b.is_some());
+    return Ok(Comparison {
+        meetings: meetings,
+        history: history,
+    });
+}
+
+/// The ratings of the teams which played in a season, as it ends.
+fn season_end(team_list: &mut Teams, season: usize, seen: (bool, bool), first: &String,
+              second: &String) -> (usize, Option<SeasonRating>, Option<SeasonRating>) {
+    let standing = team_list.standing();
+    let first_rating = if seen.0 {
+        Some(season_rating(team_list, &standing, season, first))
+    } else {
+        None
+    };
+    let second_rating = if seen.1 {
+        Some(season_rating(team_list, &standing, season, second))
+    } else {
+        None
+    };
+    return (season, first_rating, second_rating);
+}
@@ -0,0 +1,267 @@
+use super::models::{Event, Matche, Ranking, AllianceTeam, Award};
+use super::db_connect;
+use error::Result;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use probability::prelude::*;
+use probability::distribution::Gaussian;
+use std::collections::HashMap;
+
+/// Controls the spread of qualification points.
+const QUAL_ALPHA: f64 = 1.07;
+/// Points earned by each team on an alliance for every playoff match won.
+pub const PLAYOFF_WIN: i32 = 5;
+/// Points earned for every playoff round advanced in 2015, when
+/// series were decided by aggregate score rather than match wins.
+const PLAYOFF_ROUND: i32 = 10;
+/// Points for the Chairman's Award.
+pub const CHAIRMANS: i32 = 10;
+/// Points for the Engineering Inspiration and Rookie All-Star awards.
+pub const ENGINEERING_INSPIRATION: i32 = 8;
+/// Points for every other judged award.
+pub const JUDGED: i32 = 5;
+/// The number of other judged awards handed out at a typical event.
+pub const JUDGED_AWARDS: usize = 9;
+/// District championship points are worth this many times as much.
+pub const DCMP_MULTIPLIER: i32 = 3;
+/// Only this many district events count toward a team's total.
+pub const COUNTED_EVENTS: usize = 2;
+/// The Blue Alliance award types for judged team awards
+/// which are worth `JUDGED` points.
+const JUDGED_TYPES: &'static [i32] = &[11, 13, 15, 16, 17, 18, 20, 21, 22, 27, 29, 30];
+/// The Blue Alliance award type for the event winners.
+const WINNER_TYPE: i32 = 1;
+
+#[derive(Serialize, Clone)]
+pub struct Standing {
+    pub team: String,
+    pub events: Vec<(String, i32)>,
+    pub total: i32,
+}
+
+fn inverse_erf(x: f64) -> f64 {
+    let distribution = Gaussian::new(0.0, 1.0);
+    return distribution.inverse((x + 1f64) / 2f64) / 2f64.sqrt();
+}
+
+/// Points earned for finishing qualifications at `rank` (starting at 1)
+/// out of `teams` teams.
+///
+/// Before 2014 teams instead earned two points for every
+/// qualification win and one for every tie.
+pub fn qualification_points(year: i32, rank: usize, teams: usize,
+                            wins: i32, ties: i32) -> i32 {
+    if year < 2014 {
+        return 2 * wins + ties;
+    }
+    let n = teams as f64;
+    let r = rank as f64;
+    let value = inverse_erf((n - 2f64 * r + 2f64) / (QUAL_ALPHA * n))
+        * (10f64 / inverse_erf(1f64 / QUAL_ALPHA)) + 12f64;
+    return value.ceil() as i32;
+}
+
+/// Points earned during alliance selection.
+///
+/// `alliance` and `pick` both start at 0; a `pick` of 0 is the captain.
+pub fn alliance_points(alliance: usize, pick: usize) -> i32 {
+    match pick {
+        0 | 1 => 16 - alliance as i32,
+        2 => alliance as i32 + 1,
+        _ => 0,
+    }
+}
+
+/// Points earned for a single award.
+pub fn award_points(award_type: i32) -> i32 {
+    match award_type {
+        0 => CHAIRMANS,
+        9 | 10 => ENGINEERING_INSPIRATION,
+        t if JUDGED_TYPES.contains(&t) => JUDGED,
+        _ => 0,
+    }
+}
+
+/// Compute the official district points earned at an event
+/// from its stored rankings, alliances, playoff matches and awards.
+pub fn event_points(conn: &SqliteConnection, event: &Event) -> Result<HashMap<String, i32>> {
+    use schema::rankings::dsl as r;
+    use schema::alliance_teams::dsl as a;
+    use schema::awards::dsl as aw;
+    use schema::matches::dsl as m;
+    let year = event.year;
+    let mut points: HashMap<String, i32> = HashMap::new();
+    let ranking_list = r::rankings
+        .select((r::team_key, r::rank, r::wins, r::losses, r::ties, r::matches_played,
+                 r::ranking_score, r::tiebreaker))
+        .filter(r::event_id.eq(&event.id))
+        .load::<Ranking>(conn)?;
+    for ranking in &ranking_list {
+        *points.entry(ranking.team_key.clone()).or_insert(0) +=
+            qualification_points(year, ranking.rank as usize, ranking_list.len(),
+                                 ranking.wins, ranking.ties);
+    }
+    let alliance_list = a::alliance_teams
+        .select((a::alliance, a::pick, a::team_key))
+        .filter(a::event_id.eq(&event.id))
+        .load::<AllianceTeam>(conn)?;
+    for team in &alliance_list {
+        *points.entry(team.team_key.clone()).or_insert(0) +=
+            alliance_points(team.alliance as usize, team.pick as usize);
+    }
+    let award_list = aw::awards
+        .select((aw::award_type, aw::team_key))
+        .filter(aw::event_id.eq(&event.id))
+        .load::<Award>(conn)?;
+    for award in &award_list {
+        *points.entry(award.team_key.clone()).or_insert(0) += award_points(award.award_type);
+    }
+    let playoffs = Matche::played()
+        .filter(m::event_id.eq(&event.id))
+        .filter(m::comp_level.ne("qm"))
+        .load::<Matche>(conn)?;
+    let alliance_of = |team: &String| {
+        alliance_list.iter().find(|t| &t.team_key == team).map(|t| t.alliance)
+    };
+    let mut earned: HashMap<i32, i32> = HashMap::new();
+    if year == 2015 {
+        // Alliances earned points for reaching each round after the quarterfinals.
+        for level in &["sf", "f"] {
+            let mut reached = Vec::new();
+            for played in playoffs.iter().filter(|x| &x.comp_level == level) {
+                for team in played.get_red().iter().chain(played.get_blue().iter()) {
+                    if let Some(alliance) = alliance_of(team) {
+                        if !reached.contains(&alliance) {
+                            reached.push(alliance);
+                        }
+                    }
+                }
+            }
+            for alliance in reached {
+                *earned.entry(alliance).or_insert(0) += PLAYOFF_ROUND;
+            }
+        }
+        let winners = award_list.iter().filter(|x| x.award_type == WINNER_TYPE)
+            .filter_map(|x| alliance_of(&x.team_key)).next();
+        if let Some(alliance) = winners {
+            *earned.entry(alliance).or_insert(0) += PLAYOFF_ROUND;
+        }
+    } else {
+        for played in &playoffs {
+            let winners = match played.actual_r() {
+                r if r > 0.9999 => played.get_red(),
+                r if r < 0.0001 => played.get_blue(),
+                _ => continue,
+            };
+            if let Some(alliance) = winners.iter().filter_map(|t| alliance_of(t)).next() {
+                *earned.entry(alliance).or_insert(0) += PLAYOFF_WIN;
+            }
+        }
+    }
+    for team in &alliance_list {
+        *points.entry(team.team_key.clone()).or_insert(0) +=
+            *earned.get(&team.alliance).unwrap_or(&0);
+    }
+    if event.event_type == 2 || event.event_type == 5 {
+        for (_, val) in points.iter_mut() {
+            *val *= DCMP_MULTIPLIER;
+        }
+    }
+    return Ok(points);
+}
+
+/// Whether an event has finished and its official points can be used.
+pub fn event_complete(conn: &SqliteConnection, event: &Event) -> Result<bool> {
+    use schema::awards::dsl::*;
+    let winners = awards
+        .select(team_key)
+        .filter(event_id.eq(&event.id))
+        .filter(award_type.eq(WINNER_TYPE))
+        .load::<String>(conn)?;
+    return Ok(winners.len() > 0);
+}
+
+/// Current standings of a district.
+///
+/// Only the first `COUNTED_EVENTS` district events of each team count,
+/// while district championship points are always included.
+pub fn standings(district_key: &str) -> Result<Vec<Standing>> {
+    use schema::events::dsl::*;
+    use schema::district_teams::dsl::{district_teams, district_id, team_key};
+    let conn = db_connect()?;
+    let members = district_teams
+        .select(team_key)
+        .filter(district_id.eq(district_key))
+        .load::<String>(&conn)?;
+    let event_list = events
+        .filter(district.eq(district_key))
+        .order(start_date)
+        .load::<Event>(&conn)?;
+    let mut table: HashMap<String, Standing> = HashMap::new();
+    for team in members {
+        table.insert(team.clone(), Standing {
+            team: team,
+            events: Vec::new(),
+            total: 0,
+        });
+    }
+    for e in &event_list {
+        let championship = e.event_type == 2 || e.event_type == 5;
+        if e.event_type != 1 && !championship {
+            continue;
+        }
+        for (team, val) in event_points(&conn, e)? {
+            if let Some(standing) = table.get_mut(&team) {
+                if championship || standing.events.len() < COUNTED_EVENTS {
+                    standing.total += val;
+                }
+                standing.events.push((e.id.clone(), val));
+            }
+        }
+    }
+    let mut teams: Vec<Standing> = table.into_iter().map(|(_, s)| s).collect();
+    teams.sort_by(|x, y| y.total.cmp(&x.total));
+    return Ok(teams);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qualification_points_match_the_official_table() {
+        // (rank, teams, points)
+        let table = [(1, 40, 22), (2, 40, 21), (10, 40, 16), (20, 40, 13), (21, 40, 12),
+                     (40, 40, 4), (1, 24, 22), (24, 24, 5)];
+        for &(rank, teams, points) in &table {
+            assert_eq!(qualification_points(2017, rank, teams, 0, 0), points,
+                       "rank {} of {}", rank, teams);
+        }
+    }
+
+    #[test]
+    fn qualification_points_before_2014_count_wins_and_ties() {
+        assert_eq!(qualification_points(2013, 1, 40, 8, 1), 17);
+        assert_eq!(qualification_points(2013, 40, 40, 0, 0), 0);
+    }
+
+    #[test]
+    fn alliance_points_match_the_official_table() {
+        // (alliance, pick, points), both starting at 0.
+        let table = [(0, 0, 16), (0, 1, 16), (0, 2, 1), (0, 3, 0), (7, 0, 9), (7, 1, 9),
+                     (7, 2, 8), (3, 2, 4)];
+        for &(alliance, pick, points) in &table {
+            assert_eq!(alliance_points(alliance, pick), points,
+                       "alliance {} pick {}", alliance, pick);
+        }
+    }
+
+    #[test]
+    fn award_points_match_the_official_table() {
+        // (award type, points)
+        let table = [(0, 10), (9, 8), (10, 8), (13, 5), (20, 5), (WINNER_TYPE, 0), (2, 0)];
+        for &(award_type, points) in &table {
+            assert_eq!(award_points(award_type), points, "award {}", award_type);
+        }
+    }
+}
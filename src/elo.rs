@@ -1,15 +1,196 @@
 use super::models::Matche;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use probability::prelude::*;
 use probability::distribution::Gaussian;
 use super::CURRENT_YEAR;
+use std::fmt;
 
 const START_SCORE: f64 = 0f64;
 const NEW_AVG: f64 = 150f64;
 const SCORE_STD: &'static [f64] = &[17.6, 50.9, 45.6, 24.6, 28.4, 46.2,
     24.4, 21.0, 2.7, 28.4, 15.5, 31.1, 49.3, 33.2, 47.0, 95.0];
 
-#[derive(Clone)]
+/// How the first rating of a new team is chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Rookie {
+    /// Every new team starts with the same rating.
+    Fixed(f64),
+    /// New teams start at this percentile (0 to 100) of the ratings
+    /// of the teams which played the season before.
+    Percentile(f64),
+}
+
+impl Rookie {
+    /// Read a policy written either as a rating, such as `150`, or as
+    /// a percentile, such as `25%`.
+    pub fn parse(s: &str) -> Option<Rookie> {
+        if s.ends_with('%') {
+            return match s[..s.len() - 1].parse::<f64>() {
+                Ok(p) if p >= 0f64 && p <= 100f64 => Some(Rookie::Percentile(p)),
+                _ => None,
+            };
+        }
+        return s.parse::<f64>().ok().map(Rookie::Fixed);
+    }
+}
+
+impl fmt::Display for Rookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rookie::Fixed(score) => write!(f, "{}", score),
+            Rookie::Percentile(p) => write!(f, "{}%", p),
+        }
+    }
+}
+
+/// Multipliers of K for each kind of event and match.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Weights {
+    pub regional: f64,
+    pub district: f64,
+    /// District championships and their divisions.
+    pub dcmp: f64,
+    /// World championship divisions.
+    pub cmp: f64,
+    pub einstein: f64,
+    pub qm: f64,
+    pub ef: f64,
+    pub qf: f64,
+    pub sf: f64,
+    pub f: f64,
+    /// Added to the multiplier for every week of the season which has
+    /// passed, so a negative value makes early events count for more.
+    pub week: f64,
+}
+
+impl Default for Weights {
+    /// Playoff matches count for a third of a qualification match.
+    fn default() -> Weights {
+        Weights {
+            regional: 1f64,
+            district: 1f64,
+            dcmp: 1f64,
+            cmp: 1f64,
+            einstein: 1f64,
+            qm: 1f64,
+            ef: 1f64 / 3f64,
+            qf: 1f64 / 3f64,
+            sf: 1f64 / 3f64,
+            f: 1f64 / 3f64,
+            week: 0f64,
+        }
+    }
+}
+
+impl Weights {
+    /// Read weights written as `name=value` pairs separated by spaces,
+    /// such as `dcmp=1.25 qf=0.5`. Anything not given keeps its default.
+    pub fn parse(s: &str) -> Option<Weights> {
+        let mut weights = Weights::default();
+        for pair in s.split_whitespace() {
+            let mut parts = pair.splitn(2, '=');
+            let name = parts.next().unwrap_or("");
+            let value = match parts.next().map(|v| v.parse::<f64>()) {
+                Some(Ok(v)) => v,
+                _ => return None,
+            };
+            match name {
+                "regional" => weights.regional = value,
+                "district" => weights.district = value,
+                "dcmp" => weights.dcmp = value,
+                "cmp" => weights.cmp = value,
+                "einstein" => weights.einstein = value,
+                "qm" => weights.qm = value,
+                "ef" => weights.ef = value,
+                "qf" => weights.qf = value,
+                "sf" => weights.sf = value,
+                "f" => weights.f = value,
+                "week" => weights.week = value,
+                _ => return None,
+            }
+        }
+        return Some(weights);
+    }
+
+    /// The multiplier for every match of an event of `event_type`
+    /// held in `week`. Other kinds of event are left alone.
+    pub fn event(&self, event_type: i32, week: i32) -> f64 {
+        let kind = match event_type {
+            0 => self.regional,
+            1 => self.district,
+            2 | 5 => self.dcmp,
+            3 => self.cmp,
+            4 => self.einstein,
+            _ => 1f64,
+        };
+        return (kind + self.week * week as f64).max(0f64);
+    }
+
+    /// The multiplier for matches of `comp_level`.
+    pub fn level(&self, comp_level: &str) -> f64 {
+        match comp_level {
+            "qm" => self.qm,
+            "ef" => self.ef,
+            "qf" => self.qf,
+            "sf" => self.sf,
+            "f" => self.f,
+            // Any other playoff round.
+            _ => self.qf,
+        }
+    }
+}
+
+/// Where ratings stand among those of every team which played a
+/// season, so ratings from different seasons can be compared.
+pub struct Standing {
+    /// Sorted from lowest to highest.
+    ratings: Vec<f64>,
+    mean: f64,
+    deviation: f64,
+}
+
+impl Standing {
+    pub fn new(ratings: Vec<f64>) -> Standing {
+        let mut ratings = ratings;
+        ratings.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        let n = ratings.len().max(1) as f64;
+        let mean = ratings.iter().sum::<f64>() / n;
+        let variance = ratings.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        Standing {
+            ratings: ratings,
+            mean: mean,
+            deviation: variance.sqrt(),
+        }
+    }
+
+    /// Standard deviations above the mean rating.
+    pub fn z(&self, rating: f64) -> f64 {
+        if self.deviation == 0f64 {
+            return 0f64;
+        }
+        return (rating - self.mean) / self.deviation;
+    }
+
+    /// The place a team with `rating` would have, starting from 1.
+    pub fn rank(&self, rating: f64) -> usize {
+        return self.ratings.iter().filter(|&&x| x > rating).count() + 1;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.ratings.len();
+    }
+
+    /// The percentage of teams rated lower.
+    pub fn percentile(&self, rating: f64) -> f64 {
+        if self.ratings.len() == 0 {
+            return 0f64;
+        }
+        let below = self.ratings.iter().take_while(|&&x| x < rating).count();
+        return below as f64 * 100f64 / self.ratings.len() as f64;
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Teams {
     pub table: HashMap<String, f64>,
     k: f64,
@@ -19,7 +200,17 @@ pub struct Teams {
     pub total: usize,
     start_year: usize,
     current_year: usize,
-    pub active_teams: Vec<bool>,
+    /// The keys of the teams which have played in `CURRENT_YEAR`.
+    pub active_teams: HashSet<String>,
+    rookie: Rookie,
+    /// The share of the way to the rookie rating a team moves for
+    /// every season it does not play.
+    decay: f64,
+    /// The rating given to teams first seen this season.
+    rookie_score: f64,
+    /// The last season each team played a match in.
+    last_seen: HashMap<String, usize>,
+    weights: Weights,
 }
 
 impl Teams {
@@ -33,42 +224,126 @@ impl Teams {
             total: 0,
             start_year: start_year,
             current_year: start_year,
-            active_teams: vec![false; 10000],
+            active_teams: HashSet::new(),
+            rookie: Rookie::Fixed(START_SCORE),
+            decay: 0f64,
+            rookie_score: START_SCORE,
+            last_seen: HashMap::new(),
+            weights: Weights::default(),
         }
     }
 
+    /// Choose how much each kind of match moves the ratings.
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+    }
+
+    pub fn weights(&self) -> &Weights {
+        return &self.weights;
+    }
+
+    /// Choose how new teams are rated and how quickly teams which
+    /// skip seasons fall back towards that rating.
+    pub fn set_policy(&mut self, rookie: Rookie, decay: f64) {
+        self.rookie = rookie;
+        self.decay = decay;
+        if let Rookie::Fixed(score) = rookie {
+            self.rookie_score = score;
+        }
+    }
+
+    /// Describes everything which affects the ratings other than
+    /// the matches themselves. Ratings computed with different
+    /// parameters cannot be reused, nor can those computed when
+    /// `CURRENT_YEAR` was different, as it decides which teams are active.
+    pub fn parameters(&self) -> String {
+        return format!("k={} carry_over={} start={} avg={} std={:?} rookie={} decay={} \
+                        current_year={} {:?}",
+                       self.k, self.carry_over, START_SCORE, NEW_AVG, SCORE_STD,
+                       self.rookie, self.decay, CURRENT_YEAR, self.weights);
+    }
+
+    /// Start a new season for every season which has passed before `year`.
+    pub fn advance_to(&mut self, year: usize) {
+        while self.current_year < year {
+            self.new_year();
+        }
+    }
+
+    /// Rate matches in the order given. Each match comes with the
+    /// season it was played in and the weight of its result.
+    pub fn replay<'a, I>(&mut self, match_list: I)
+        where I: IntoIterator<Item = (usize, f64, &'a Matche)> {
+        self.replay_each(match_list, |_, _, _| {});
+    }
+
+    /// Rate matches like `replay`, first handing each to `before` along
+    /// with its season. `before` is called ahead of any season the match
+    /// begins, so the ratings as the last season ended can still be read.
+    pub fn replay_each<'a, I, F>(&mut self, match_list: I, mut before: F)
+        where I: IntoIterator<Item = (usize, f64, &'a Matche)>,
+              F: FnMut(&mut Teams, usize, &'a Matche) {
+        for (year, weight, m) in match_list {
+            before(self, year, m);
+            self.advance_to(year);
+            self.process_weighted(m, weight);
+        }
+    }
+
+    /// The ratings of every team which has played this season.
+    pub fn standing(&self) -> Standing {
+        let year = self.current_year;
+        return Standing::new(self.table.iter()
+                             .filter(|&(team, _)| self.last_seen.get(team) == Some(&year))
+                             .map(|(_, val)| *val)
+                             .collect());
+    }
+
     pub fn new_year(&mut self) {
         for (_, val) in self.table.iter_mut() {
             *val = *val * self.carry_over + NEW_AVG * (1f64 - self.carry_over);
         }
+        let year = self.current_year;
+        let last_seen = &self.last_seen;
+        if let Rookie::Percentile(p) = self.rookie {
+            let mut played: Vec<f64> = self.table.iter()
+                .filter(|&(team, _)| last_seen.get(team) == Some(&year))
+                .map(|(_, val)| *val)
+                .collect();
+            if let Some(score) = percentile(&mut played, p) {
+                self.rookie_score = score;
+            }
+        }
+        if self.decay > 0f64 {
+            for (team, val) in self.table.iter_mut() {
+                if last_seen.get(team) != Some(&year) {
+                    *val += (self.rookie_score - *val) * self.decay;
+                }
+            }
+        }
         self.current_year += 1;
     }
 
     pub fn get(&mut self, team: &String) -> f64 {
-        let entry = self.table.entry(team.to_owned()).or_insert(START_SCORE);
+        let score = self.rookie_score;
+        let entry = self.table.entry(team.to_owned()).or_insert(score);
         return *entry;
     }
 
     pub fn update(&mut self, team: &String, change: f64) {
-        let mut entry = self.table.entry(team.to_owned()).or_insert(START_SCORE);
-        *entry += change;
+        let score = self.rookie_score;
+        *self.table.entry(team.to_owned()).or_insert(score) += change;
+        self.last_seen.insert(team.to_owned(), self.current_year);
         if self.current_year == CURRENT_YEAR as usize {
-            self.active_teams[team.replace("frc", "").parse::<usize>().unwrap()] = true;
+            self.active_teams.insert(team.to_owned());
         }
     }
 
     pub fn sum_elo(&mut self, m: &Matche, red: bool) -> f64 {
-        let mut score;
-        if red {
-            score = self.get(&m.red1) + self.get(&m.red2);
-            if let Some(ref t) = m.red3 {
-                score += self.get(t);
-            }
-        } else {
-            score = self.get(&m.blue1) + self.get(&m.blue2);
-            if let Some(ref t) = m.blue3 {
-                score += self.get(t);
-            }
+        let alliance = if red { m.get_red() } else { m.get_blue() };
+        let mut score = 0f64;
+        for team in &alliance {
+            score += self.get(team);
         }
         return score;
     }
@@ -86,34 +361,28 @@ impl Teams {
     }
 
     pub fn process_match(&mut self, m: &Matche) {
+        self.process_weighted(m, 1f64);
+    }
+
+    /// Rate a match whose rating changes are scaled by `weight`.
+    pub fn process_weighted(&mut self, m: &Matche, weight: f64) {
         let m = m.clone();
         let expected_r = self.predict(&m);
         let actual_r = m.actual_r();
-        let modifier;
-        if m.comp_level == "qm" {
-            modifier = 1f64;
-        } else {
-            modifier = 3f64;
-        }
         let predicted_score_diff = self.predict_diff(expected_r);
         let score_margin_adj = (m.score_margin() as f64 - predicted_score_diff)
             / SCORE_STD[self.current_year - self.start_year];
-        let change_r = self.k * score_margin_adj / modifier;
-        self.update(&m.red1, change_r);
-        self.update(&m.red2, change_r);
-        if let Some(ref m) = m.red3 {
-            self.update(m, change_r);
-        };
-        self.update(&m.blue1, -change_r);
-        self.update(&m.blue2, -change_r);
-        match m.blue3 {
-            Some(ref m) => self.update(m, -change_r),
-            None => {},
-        };
+        let change_r = self.k * score_margin_adj * self.weights.level(&m.comp_level) * weight;
+        for team in &m.get_red() {
+            self.update(team, change_r);
+        }
+        for team in &m.get_blue() {
+            self.update(team, -change_r);
+        }
         // TODO: Allow this to be enabled using a flag.
         //if m.comp_level != "qm" &&
         //if m.id.contains("2012") || m.id.contains("2013") || m.id.contains("2014") {
-        if m.id.contains("2017") {
+        if self.current_year == CURRENT_YEAR as usize {
             if m.actual_r() > 0.4 && m.actual_r() < 0.6 {
                 return;
             }
@@ -140,3 +409,187 @@ impl Teams {
         return actual > 0.0f64;
     }
 }
+
+/// The value below which `p` percent of `values` fall.
+fn percentile(values: &mut Vec<f64>, p: f64) -> Option<f64> {
+    if values.len() == 0 {
+        return None;
+    }
+    values.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    let index = (p / 100f64 * (values.len() - 1) as f64).round() as usize;
+    return Some(values[index]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::played;
+
+    fn red_win(event: &str, number: i32) -> Matche {
+        played(event, number, &["frc1", "frc2", "frc3"], &["frc4", "frc5", "frc6"], 100, 50)
+    }
+
+    #[test]
+    fn replay_starts_a_season_for_every_elapsed_year() {
+        let match_list = vec![red_win("2002ca", 1), red_win("2004ca", 1)];
+        let mut expected = Teams::new(15f64, 0.8f64, 2002);
+        expected.process_match(&match_list[0]);
+        expected.new_year();
+        expected.new_year();
+        expected.process_match(&match_list[1]);
+
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.replay(vec![(2002, 1f64, &match_list[0]), (2004, 1f64, &match_list[1])]);
+        assert_eq!(teams.current_year, 2004);
+        for team in &["frc1", "frc4"] {
+            let team = team.to_string();
+            assert_eq!(teams.get(&team), expected.get(&team));
+        }
+    }
+
+    #[test]
+    fn replay_uses_the_given_season_rather_than_the_event_key() {
+        let match_list = vec![red_win("2003ca", 1), red_win("2005cmp2003", 1),
+                              red_win("2005cmp2003", 2)];
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.replay(vec![(2003, 1f64, &match_list[0]), (2005, 1f64, &match_list[1]),
+                          (2005, 1f64, &match_list[2])]);
+        assert_eq!(teams.current_year, 2005);
+    }
+
+    #[test]
+    fn replay_never_goes_back_a_season() {
+        let match_list = vec![red_win("2006ca", 1), red_win("2005mi", 1)];
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.replay(vec![(2006, 1f64, &match_list[0]), (2005, 1f64, &match_list[1])]);
+        assert_eq!(teams.current_year, 2006);
+    }
+
+    #[test]
+    fn replay_of_many_seasons_reaches_the_current_year() {
+        let mut match_list = Vec::new();
+        for year in (2002..CURRENT_YEAR + 1).filter(|y| y % 4 != 3) {
+            for number in 1..4 {
+                match_list.push((year as usize, red_win(&format!("{}ca", year), number)));
+            }
+        }
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.replay(match_list.iter().map(|&(year, ref m)| (year, 1f64, m)));
+        assert_eq!(teams.current_year, CURRENT_YEAR as usize);
+        assert!(teams.active_teams.contains("frc1"));
+        assert!(teams.get(&String::from("frc1")) > teams.get(&String::from("frc4")));
+        assert!(teams.total > 0);
+    }
+
+    #[test]
+    fn rookie_policies_are_parsed() {
+        assert_eq!(Rookie::parse("150"), Some(Rookie::Fixed(150f64)));
+        assert_eq!(Rookie::parse("25%"), Some(Rookie::Percentile(25f64)));
+        assert_eq!(Rookie::parse("125%"), None);
+        assert_eq!(Rookie::parse("high"), None);
+    }
+
+    #[test]
+    fn fixed_rookies_start_at_the_given_rating() {
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.set_policy(Rookie::Fixed(100f64), 0f64);
+        assert_eq!(teams.get(&String::from("frc1")), 100f64);
+    }
+
+    #[test]
+    fn percentile_rookies_start_within_last_season() {
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.set_policy(Rookie::Percentile(0f64), 0f64);
+        teams.replay(vec![(2002, 1f64, &red_win("2002ca", 1)),
+                          (2002, 1f64, &red_win("2002ca", 2))]);
+        teams.new_year();
+        let lowest = teams.get(&String::from("frc4"));
+        assert!(lowest < teams.get(&String::from("frc1")));
+        assert_eq!(teams.get(&String::from("frc7")), lowest);
+    }
+
+    #[test]
+    fn teams_which_skip_a_season_decay_towards_the_rookie_rating() {
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.set_policy(Rookie::Fixed(0f64), 0.5f64);
+        let other = played("2003ca", 1, &["frc1", "frc2", "frc3"], &["frc7", "frc8", "frc9"],
+                           50, 50);
+        teams.replay(vec![(2002, 1f64, &red_win("2002ca", 1)), (2003, 1f64, &other)]);
+        let mut kept = Teams::new(15f64, 0.8f64, 2002);
+        kept.replay(vec![(2002, 1f64, &red_win("2002ca", 1)), (2003, 1f64, &other)]);
+        teams.new_year();
+        kept.new_year();
+        let (team, idle) = (String::from("frc1"), String::from("frc4"));
+        assert_eq!(teams.get(&team), kept.get(&team));
+        assert_eq!(teams.get(&idle), kept.get(&idle) / 2f64);
+    }
+
+    #[test]
+    fn every_team_of_an_alliance_is_rated_whatever_its_size() {
+        let m = played("2017ca", 1, &["frc1", "frc2", "frc3", "frc4"], &["frc5", "frc6"],
+                       100, 50);
+        let mut teams = Teams::new(15f64, 0.8f64, CURRENT_YEAR as usize);
+        teams.process_match(&m);
+        let gain = teams.get(&String::from("frc1"));
+        assert!(gain > 0f64);
+        assert_eq!(teams.get(&String::from("frc4")), gain);
+        assert_eq!(teams.get(&String::from("frc6")), -gain);
+        assert_eq!(teams.sum_elo(&m, true), 4f64 * gain);
+        assert_eq!(teams.sum_elo(&m, false), -2f64 * gain);
+    }
+
+    #[test]
+    fn weighted_matches_move_ratings_less() {
+        let m = red_win("2017ca", 1);
+        let mut full = Teams::new(15f64, 0.8f64, CURRENT_YEAR as usize);
+        full.process_match(&m);
+        let mut half = Teams::new(15f64, 0.8f64, CURRENT_YEAR as usize);
+        half.replay(vec![(CURRENT_YEAR as usize, 0.5f64, &m)]);
+        let team = String::from("frc1");
+        assert_eq!(half.get(&team), full.get(&team) / 2f64);
+    }
+
+    #[test]
+    fn weights_are_parsed_over_the_defaults() {
+        let weights = Weights::parse("dcmp=1.25 qf=0.5 week=-0.125").unwrap();
+        assert_eq!(weights.dcmp, 1.25f64);
+        assert_eq!(weights.qf, 0.5f64);
+        assert_eq!(weights.sf, Weights::default().sf);
+        assert_eq!(weights.event(2, 2), 1f64);
+        assert_eq!(weights.event(0, 10), 0f64);
+        assert_eq!(weights.event(5, 0), 1.25f64);
+        assert_eq!(Weights::parse(""), Some(Weights::default()));
+        assert_eq!(Weights::parse("semis=1"), None);
+        assert_eq!(Weights::parse("qf"), None);
+    }
+
+    #[test]
+    fn standing_places_ratings_within_their_season() {
+        let standing = Standing::new(vec![30f64, 10f64, 20f64, 40f64]);
+        assert_eq!(standing.z(25f64), 0f64);
+        assert!(standing.z(40f64) > 1f64);
+        assert_eq!(standing.percentile(10f64), 0f64);
+        assert_eq!(standing.percentile(40f64), 75f64);
+        assert_eq!(standing.rank(40f64), 1);
+        assert_eq!(standing.rank(15f64), 4);
+        assert_eq!(Standing::new(vec![5f64, 5f64]).z(5f64), 0f64);
+    }
+
+    #[test]
+    fn standing_only_counts_teams_which_played_this_season() {
+        let mut teams = Teams::new(15f64, 0.8f64, 2002);
+        teams.replay(vec![(2002, 1f64, &red_win("2002ca", 1)),
+                          (2003, 1f64, &played("2003ca", 1, &["frc1"], &["frc7"], 10, 5))]);
+        assert_eq!(teams.standing().ratings.len(), 2);
+    }
+
+    #[test]
+    fn teams_with_letters_in_their_keys_are_rated() {
+        let m = played("2017ca", 1, &["frc1678B", "frc2", "frc3"], &["frc4", "frc5", "frc6"],
+                       100, 50);
+        let mut teams = Teams::new(15f64, 0.8f64, CURRENT_YEAR as usize);
+        teams.process_match(&m);
+        assert!(teams.active_teams.contains("frc1678B"));
+        assert!(teams.get(&String::from("frc1678B")) > 0f64);
+    }
+}
@@ -0,0 +1,97 @@
+use curl;
+use diesel;
+use serde_json;
+use std::{error, fmt, io, result, str};
+
+/// Everything which can go wrong while syncing or rating.
+#[derive(Debug)]
+pub enum Error {
+    /// A request to The Blue Alliance could not be completed.
+    Network(curl::Error),
+    /// The Blue Alliance answered a URL with an unexpected status code.
+    Status(String, u32),
+    /// A response was not valid UTF-8.
+    Encoding(str::Utf8Error),
+    /// A response could not be parsed.
+    Json(serde_json::Error),
+    /// A database query failed.
+    Database(diesel::result::Error),
+    /// The database could not be opened.
+    Connection(diesel::ConnectionError),
+    /// A required setting is missing or invalid.
+    Config(String),
+    /// A local file could not be read or written.
+    Io(io::Error),
+    /// A worker thread panicked.
+    Thread,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Network(ref e) => write!(f, "Network error: {}", e),
+            Error::Status(ref url, code) => write!(f, "Request for {} returned {}", url, code),
+            Error::Encoding(ref e) => write!(f, "Invalid response: {}", e),
+            Error::Json(ref e) => write!(f, "Could not parse response: {}", e),
+            Error::Database(ref e) => write!(f, "Database error: {}", e),
+            Error::Connection(ref e) => write!(f, "Could not connect to database: {}", e),
+            Error::Config(ref s) => write!(f, "{}", s),
+            Error::Io(ref e) => write!(f, "File error: {}", e),
+            Error::Thread => write!(f, "A worker thread panicked"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Network(_) => "network error",
+            Error::Status(..) => "unexpected status code",
+            Error::Encoding(_) => "invalid response encoding",
+            Error::Json(_) => "invalid JSON",
+            Error::Database(_) => "database error",
+            Error::Connection(_) => "database connection error",
+            Error::Config(_) => "configuration error",
+            Error::Io(_) => "file error",
+            Error::Thread => "thread panicked",
+        }
+    }
+}
+
+impl From<curl::Error> for Error {
+    fn from(e: curl::Error) -> Error {
+        Error::Network(e)
+    }
+}
+
+impl From<str::Utf8Error> for Error {
+    fn from(e: str::Utf8Error) -> Error {
+        Error::Encoding(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Json(e)
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Error {
+        Error::Database(e)
+    }
+}
+
+impl From<diesel::ConnectionError> for Error {
+    fn from(e: diesel::ConnectionError) -> Error {
+        Error::Connection(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
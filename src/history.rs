@@ -0,0 +1,59 @@
+use super::elo::{Teams, Standing};
+use super::{get_matches, replay_matches};
+use error::Result;
+
+/// A team's rating at the end of a season and where it stood that season.
+pub struct SeasonRating {
+    pub year: usize,
+    pub rating: f64,
+    /// Standard deviations above the mean rating of the season's teams.
+    pub z: f64,
+    /// The percentage of the season's teams rated lower.
+    pub percentile: f64,
+    pub rank: usize,
+    /// The number of teams which played the season.
+    pub teams: usize,
+}
+
+/// Where `team` stands among the teams of the season being rated.
+pub fn season_rating(team_list: &mut Teams, standing: &Standing, year: usize,
+                     team: &String) -> SeasonRating {
+    let rating = team_list.get(team);
+    return SeasonRating {
+        year: year,
+        rating: rating,
+        z: standing.z(rating),
+        percentile: standing.percentile(rating),
+        rank: standing.rank(rating),
+        teams: standing.len(),
+    };
+}
+
+/// Replay every match, noting the rating of `team` as each season
+/// it played in ends.
+pub fn history(team: &str, offseason: Option<f64>) -> Result<Vec<SeasonRating>> {
+    let (event_list, match_list) = get_matches(offseason.is_some())?;
+    let team = team.to_owned();
+    let mut seasons = Vec::new();
+    let mut current = None;
+    let mut seen = false;
+    let mut team_list = replay_matches(&event_list, &match_list, offseason,
+                                       |team_list, season, m| {
+        if let Some(year) = current {
+            if year < season && seen {
+                let standing = team_list.standing();
+                seasons.push(season_rating(team_list, &standing, year, &team));
+            }
+            if year < season {
+                seen = false;
+            }
+        }
+        current = Some(season);
+        seen = seen || m.get_red().contains(&team) || m.get_blue().contains(&team);
+    })?;
+    if let (Some(year), true) = (current, seen) {
+        let standing = team_list.standing();
+        seasons.push(season_rating(&mut team_list, &standing, year, &team));
+    }
+    return Ok(seasons);
+}
@@ -1,11 +1,10 @@
 extern crate curl;
-extern crate rustc_serialize;
 extern crate dotenv;
+extern crate serde;
 #[macro_use] extern crate serde_derive;
 extern crate serde_json;
 #[macro_use] extern crate diesel;
 #[macro_use] extern crate diesel_codegen;
-extern crate csv;
 extern crate pbr;
 #[macro_use] extern crate clap;
 extern crate rand;
@@ -17,26 +16,48 @@ mod tba;
 mod schema;
 mod models;
 mod elo;
+mod sim;
+mod district;
+mod season;
+mod schedule;
+mod watch;
+mod checkpoint;
+mod error;
+mod client;
+mod source;
+mod writer;
+mod backtest;
+mod summary;
+mod strength;
+mod compare;
+mod history;
+#[cfg(test)]
+mod testing;
 
 use diesel::sqlite::SqliteConnection;
 use dotenv::dotenv;
 use diesel::prelude::*;
 use models::*;
-use elo::Teams;
+use elo::{Teams, Rookie, Weights};
 use tba::TeamEventRanking;
-use std::{thread, str, env};
-use std::fs::OpenOptions;
-use std::error::Error;
-use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
+use source::{DataSource, TbaSource, FixtureSource};
+use client::{Mode, Stamp};
+use clap::ArgMatches;
+use std::{thread, str, env, process};
+use std::path::PathBuf;
+use error::{Error, Result};
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use std::time::Instant;
+use std::collections::{BTreeSet, HashMap};
 use std::clone::Clone;
 use schema::matches::dsl::*;
 use schema::events::dsl::*;
-use std::cmp::Ordering;
 use clap::App;
-use rand::Rng;
 use tera::Context;
 use chrono::offset::utc::UTC;
+use chrono::NaiveDate;
+use std::cmp::Ordering;
 
 /// The first year for which data exists.
 /// This is used by the `sync` command as the first
@@ -50,15 +71,26 @@ const CURRENT_YEAR: i32 = 2017;
 const NEXT_YEAR: i32 = CURRENT_YEAR + 1;
 /// The number of simulations to run when modeling. 
 const EST_RUNS: usize = 10000;
+/// How far the ratings move after a single match.
+const K: f64 = 15f64;
+/// The share of each rating kept from one season to the next.
+const CARRY_OVER: f64 = 0.8f64;
 
 /// Holds events and matches which will eventually
 /// need to be added to the database.
-#[derive(Clone)]
 struct RequestData {
     /// A list of event responses from parsed JSON.
     events: Vec<models::EventJSON>,
     /// A list of match responses from parsed JSON.
     matches: Vec<models::GameMatch>,
+    /// Rankings prepared for insertion.
+    rankings: Vec<models::NewRanking>,
+    /// Alliance members prepared for insertion.
+    alliances: Vec<models::NewAllianceTeam>,
+    /// Award recipients prepared for insertion.
+    awards: Vec<models::NewAward>,
+    /// Teams attending prepared for insertion.
+    teams: Vec<models::NewEventTeam>,
 }
 
 impl RequestData {
@@ -67,197 +99,556 @@ impl RequestData {
         RequestData {
             events: Vec::new(),
             matches: Vec::new(),
+            rankings: Vec::new(),
+            alliances: Vec::new(),
+            awards: Vec::new(),
+            teams: Vec::new(),
         }
     }
 }
 
-#[derive(RustcDecodable)]
-struct HistoryRecord {
-    url: String,
-    time: String,
+/// Which events a sync should read.
+#[derive(Clone)]
+struct Selection {
+    /// Seasons to read.
+    years: Vec<i32>,
+    /// Only read this event.
+    event: Option<String>,
+    /// Only read events ending on or after this date.
+    since: Option<String>,
+    /// Only read events in this week.
+    week: Option<i32>,
+}
+
+impl Selection {
+    /// Read the selection given to `sync`. Every season is read by default.
+    fn from_args(m: &ArgMatches) -> Result<Selection> {
+        let mut selection = Selection {
+            years: (FIRST_YEAR..NEXT_YEAR).collect(),
+            event: None,
+            since: None,
+            week: None,
+        };
+        if let Some(y) = m.value_of("year") {
+            match y.parse::<i32>() {
+                Ok(season) if season >= FIRST_YEAR && season <= CURRENT_YEAR => selection.years = vec![season],
+                _ => return Err(Error::Config(format!("Invalid year: {}", y))),
+            }
+        }
+        if let Some(w) = m.value_of("week") {
+            match w.parse::<i32>() {
+                Ok(w) => selection.week = Some(w),
+                Err(_) => return Err(Error::Config(format!("Invalid week: {}", w))),
+            }
+            if m.value_of("year").is_none() && m.value_of("since").is_none() {
+                selection.years = vec![CURRENT_YEAR];
+            }
+        }
+        if let Some(date) = m.value_of("since") {
+            let season = match date.get(..4).and_then(|y| y.parse::<i32>().ok()) {
+                Some(y) if date.len() == 10 && y <= CURRENT_YEAR => y,
+                _ => return Err(Error::Config(format!("Invalid date: {}", date))),
+            };
+            selection.years = (season.max(FIRST_YEAR)..NEXT_YEAR).collect();
+            selection.since = Some(date.to_owned());
+        }
+        if let Some(key) = m.value_of("event") {
+            match key.get(..4).and_then(|y| y.parse::<i32>().ok()) {
+                Some(season) if season >= FIRST_YEAR && season <= CURRENT_YEAR => selection.years = vec![season],
+                _ => return Err(Error::Config(format!("Invalid event key: {}", key))),
+            }
+            selection.event = Some(key.to_owned());
+        }
+        return Ok(selection);
+    }
+
+    /// Whether every event in the selected seasons is read.
+    fn complete(&self) -> bool {
+        return self.event.is_none() && self.since.is_none() && self.week.is_none();
+    }
+
+    fn includes(&self, event: &EventJSON) -> bool {
+        if let Some(ref key) = self.event {
+            if &event.key != key {
+                return false;
+            }
+        }
+        if let Some(ref date) = self.since {
+            if event.end_date.as_ref().unwrap_or(&event.start_date) < date {
+                return false;
+            }
+        }
+        if let Some(w) = self.week {
+            if event.week != Some(w) {
+                return false;
+            }
+        }
+        return true;
+    }
 }
 
 #[derive(Serialize, Clone)]
 struct TableEntry {
     team: String,
+    nickname: String,
     rating: f64,
+    /// The rating in standard deviations from the mean of this season.
+    z: f64,
+    /// The percentage of this season's teams rated lower.
+    percentile: f64,
     sim: Option<SimulatedResult>,
 }
 
-/// Get the hash map containing the URLs and time strings.
-/// Values are read form a CSV file named `tba_history.csv`.
-fn open_history() -> HashMap<String, String> {
-    let f = OpenOptions::new().create(true).write(true).open("tba_history.csv").unwrap();
-    drop(f);
-    let mut map: HashMap<String, String> = HashMap::new();
-    let mut rdr = csv::Reader::from_file("tba_history.csv").unwrap();
-    for record in rdr.decode() {
-        let record: HistoryRecord = match record {
-            Ok(r) => r,
-            Err(e) => {
-                panic!("ERROR: {}", e.description());
-                //continue;
-            },
-        };
-        map.insert(record.url, record.time);
+/// Get the hash map containing the URLs and their stamps.
+/// Values are read from the `sync_state` table.
+fn open_history() -> Result<HashMap<String, Stamp>> {
+    let conn = db_connect()?;
+    let mut map: HashMap<String, Stamp> = HashMap::new();
+    for state in schema::sync_state::table.load::<SyncState>(&conn)? {
+        map.insert(state.url, Stamp {
+            last_modified: state.last_modified,
+            etag: state.etag,
+        });
     }
-    return map;
+    return Ok(map);
 }
 
-/// Given a hash map, record to a CSV file named `tba_history.csv`
-fn write_history(map: &HashMap<String, String>) {
-    let mut wtr = csv::Writer::from_file("tba_history.csv").unwrap();
-    for record in map.iter() {
-        let _ = wtr.encode(record);
+/// The teams attending an event, as stored during sync.
+fn event_teams(conn: &SqliteConnection, event_key: &str) -> Result<Vec<String>> {
+    use schema::event_teams::dsl as t;
+    return Ok(t::event_teams
+        .select(t::team_key)
+        .filter(t::event_id.eq(event_key))
+        .load::<String>(conn)?);
+}
+
+/// The latest rankings of an event, as stored during sync.
+fn event_rankings(conn: &SqliteConnection, event_key: &str)
+                  -> Result<HashMap<String, TeamEventRanking>> {
+    use schema::rankings::dsl as r;
+    let mut rankings = HashMap::new();
+    let ranking_list = r::rankings
+        .select((r::team_key, r::rank, r::wins, r::losses, r::ties, r::matches_played,
+                 r::ranking_score, r::tiebreaker))
+        .filter(r::event_id.eq(event_key))
+        .load::<Ranking>(conn)?;
+    for ranking in ranking_list {
+        rankings.insert(ranking.team_key.clone(), TeamEventRanking::from_ranking(&ranking));
+    }
+    return Ok(rankings);
+}
+
+/// The nickname of every synced team keyed by team key.
+fn team_names() -> Result<HashMap<String, String>> {
+    use schema::teams::dsl as t;
+    let conn = db_connect()?;
+    let mut names = HashMap::new();
+    let team_list = t::teams
+        .select((t::key, t::nickname))
+        .load::<(String, Option<String>)>(&conn)?;
+    for (team, team_nickname) in team_list {
+        if let Some(team_nickname) = team_nickname {
+            names.insert(team, team_nickname);
+        }
     }
+    return Ok(names);
+}
+
+/// The nickname of a team, or nothing if it is not known.
+fn nickname(names: &HashMap<String, String>, team: &str) -> String {
+    return names.get(team).cloned().unwrap_or(String::new());
 }
 
-fn db_connect() -> SqliteConnection {
+fn db_connect() -> Result<SqliteConnection> {
     dotenv().ok();
-    let database_url = env::var("DATABASE_URL").
-        expect("DATABASE_URL must be set");
-    SqliteConnection::establish(&database_url)
-        .expect(&format!("Error ocnnecting to {}", database_url))
+    let database_url = match env::var("DATABASE_URL") {
+        Ok(url) => url,
+        Err(_) => return Err(Error::Config(String::from("DATABASE_URL must be set"))),
+    };
+    return Ok(SqliteConnection::establish(&database_url)?);
 }
 
-fn setup() {
+/// Sync the selected events from The Blue Alliance.
+///
+/// A failure to sync one event does not stop the others.
+/// Returns the events or other resources which failed.
+fn setup(source: Arc<dyn DataSource>, selection: Selection) -> Result<Vec<(String, Error)>> {
+    let started = Instant::now();
+    let conn = db_connect()?;
+    writer::prepare(&conn)?;
+    let (sender, writer_thread) = writer::spawn(conn);
+    if !selection.complete() {
+        // An unchanged list of events would otherwise skip the selected events.
+        source.forget("events/");
+    }
     let mut threads = Vec::new();
-    let history = Arc::new(Mutex::new(open_history()));
-    let conn = Arc::new(Mutex::new(db_connect()));
-    for i in 2002..NEXT_YEAR {
-        let history = history.clone();
-        let conn = conn.clone();
+    if selection.complete() {
+        let source = source.clone();
+        let sender = sender.clone();
         threads.push(thread::spawn(move || {
-            let mut info = RequestData::new();
-            if let Some(mut event_list) = tba::get_events(history.clone(), i) {
-                info.events.append(&mut event_list);
-                let mut event_threads = Vec::new();
-                for i in 0..5 {
-                    let event_list = info.events.clone();
-                    let history = history.clone();
-                    let conn = conn.clone();
-                    event_threads.push(thread::spawn(move || {
-                        let mut result = RequestData::new();
-                        for j in 0..event_list.len() / 5 + 1 {
-                            let index = i + 5 * j;
-                            if index >= event_list.len() {
-                                break;
-                            }
-                            if let Some(mut em) = tba::get_event_matches(history.clone(),
-                                                                         &event_list[index].key) {
-                                result.events.push(event_list[index].clone());
-                                result.matches.append(&mut em);
-                            }
+            let mut failures = Vec::new();
+            for page in 0.. {
+                match source.teams(page) {
+                    Ok(Some(ref teams)) if teams.len() == 0 => break,
+                    Ok(Some(teams)) => {
+                        let batch = writer::Batch::Teams {
+                            page: page,
+                            teams: teams,
+                            stamps: source.stamps(&format!("teams/{}", page)),
+                        };
+                        send(&sender, batch, &mut failures);
+                    },
+                    Ok(None) => {},
+                    Err(e) => {
+                        failures.push((format!("teams/{}", page), e));
+                        break;
+                    },
+                }
+            }
+            return failures;
+        }));
+    }
+    let team_threads = threads.len();
+    for i in selection.years.clone() {
+        let source = source.clone();
+        let sender = sender.clone();
+        let selection = selection.clone();
+        threads.push(thread::spawn(move || {
+            let mut failures: Vec<(String, Error)> = Vec::new();
+            if selection.complete() {
+                match read_districts(&*source, i) {
+                    Ok(Some(batch)) => send(&sender, batch, &mut failures),
+                    Ok(None) => {},
+                    Err(e) => failures.push((format!("districts/{}", i), e)),
+                }
+            }
+            let mut event_list = match source.events(i) {
+                Ok(Some(event_list)) => event_list,
+                Ok(None) => Vec::new(),
+                Err(e) => {
+                    failures.push((format!("events/{}", i), e));
+                    Vec::new()
+                },
+            };
+            event_list.retain(|e| selection.includes(e));
+            let mut event_threads = Vec::new();
+            for j in 0..5 {
+                let event_list = event_list.clone();
+                let source = source.clone();
+                let sender = sender.clone();
+                event_threads.push(thread::spawn(move || {
+                    let mut failures = Vec::new();
+                    for k in 0..event_list.len() / 5 + 1 {
+                        let index = j + 5 * k;
+                        if index >= event_list.len() {
+                            break;
                         }
-                        let conn = conn.lock().expect("Database connection");
-                        let new_events: Vec<NewEvent> = result.events.iter()
-                            .map(|x| prepare_event(x)).collect();
-                        diesel::insert_or_replace(&new_events)
-                            .into(events).execute(&*conn)
-                            .expect("Could not insert events");
-                        if result.matches.len() > 0 {
-                            let new_matches: Vec<NewMatch> = result.matches.iter()
-                                .filter_map(|x| prepare_match(x)).collect();
-                            diesel::insert_or_replace(&new_matches).into(matches).execute(&*conn)
-                                .expect("Could not insert mathes");
+                        let event = &event_list[index];
+                        match read_event(&*source, event) {
+                            Ok(data) => {
+                                let batch = writer::Batch::Event {
+                                    year: i,
+                                    key: event.key.clone(),
+                                    data: data,
+                                    stamps: source.stamps(&format!("event/{}/", event.key)),
+                                };
+                                send(&sender, batch, &mut failures);
+                            },
+                            Err(e) => failures.push((event.key.clone(), e)),
                         }
-                    }));
-                }
-                for child in event_threads {
-                    let _ = child.join();
+                    }
+                    return failures;
+                }));
+            }
+            for child in event_threads {
+                match child.join() {
+                    Ok(mut f) => failures.append(&mut f),
+                    Err(_) => failures.push((format!("events/{}", i), Error::Thread)),
                 }
             }
+            // Keep asking for the full list until every event has synced.
+            let stamps = if failures.len() == 0 && selection.complete() {
+                source.stamps(&format!("events/{}", i))
+            } else {
+                Vec::new()
+            };
+            send(&sender, writer::Batch::Season { year: i, stamps: stamps }, &mut failures);
+            return failures;
         }));
     }
-    for child in threads {
-        let _ = child.join();
+    drop(sender);
+    let mut failures = Vec::new();
+    for (i, child) in threads.into_iter().enumerate() {
+        match child.join() {
+            Ok(mut f) => failures.append(&mut f),
+            Err(_) if i < team_threads => failures.push((String::from("teams"), Error::Thread)),
+            Err(_) => failures.push((format!("{}", selection.years[i - team_threads]),
+                                     Error::Thread)),
+        }
     }
-    let history = history.lock().unwrap();
-    write_history(&history);
+    let stats = match writer_thread.join() {
+        Ok((mut f, stats)) => {
+            failures.append(&mut f);
+            stats
+        },
+        Err(_) => return Err(Error::Thread),
+    };
+    let elapsed = started.elapsed().as_secs_f64();
+    let writing = stats.writing.as_secs_f64();
+    println!("Wrote {} rows for {} events in {:.1}s ({:.0} rows/s, {:.1}s spent writing)",
+             stats.rows, stats.events, elapsed,
+             stats.rows as f64 / writing.max(0.001), writing);
+    return Ok(failures);
 }
 
-fn get_matches() -> (Vec<Event>, Vec<Vec<Matche>>) {
-    let conn = db_connect();
-    let event_list = events
-        .filter(official.eq(1))
-        .filter(event_type.lt(99))
-    //.filter(start_date.gt("2008"))
-        .order(start_date)
-        .load::<Event>(&conn).expect("Could not query events");
+/// Hand a batch to the writer thread.
+fn send(sender: &Sender<writer::Batch>, batch: writer::Batch,
+        failures: &mut Vec<(String, Error)>) {
+    if sender.send(batch).is_err() {
+        failures.push((String::from("database writer"), Error::Thread));
+    }
+}
+
+/// Read the districts of a season along with their teams.
+fn read_districts(source: &dyn DataSource, season: i32) -> Result<Option<writer::Batch>> {
+    let district_list = match source.districts(season)? {
+        Some(d) => d,
+        None => return Ok(None),
+    };
+    let mut member_lists = Vec::new();
+    for d in &district_list {
+        if let Some(team_keys) = source.district_teams(&d.key)? {
+            member_lists.push((d.key.clone(), team_keys));
+        }
+    }
+    let mut stamps = source.stamps(&format!("districts/{}", season));
+    stamps.append(&mut source.stamps(&format!("district/{}", season)));
+    return Ok(Some(writer::Batch::Districts {
+        year: season,
+        districts: district_list,
+        members: member_lists,
+        stamps: stamps,
+    }));
+}
+
+/// Read a single event. Nothing is stored unless every
+/// request for the event succeeds, in which case the event
+/// will be requested in full during the next sync.
+fn read_event(source: &dyn DataSource, event: &EventJSON) -> Result<RequestData> {
+    let key = &event.key;
+    let mut result = RequestData::new();
+    if let Some(mut em) = source.event_matches(key)? {
+        result.events.push(event.clone());
+        result.matches.append(&mut em);
+    }
+    if let Some(r) = source.rankings(key)? {
+        result.rankings.extend(r.rankings.iter().map(|x| x.prepare(key)));
+    }
+    if let Some(a) = source.alliances(key)? {
+        result.alliances.append(&mut prepare_alliances(key, &a));
+    }
+    if let Some(a) = source.awards(key)? {
+        result.awards.append(&mut prepare_awards(&a));
+    }
+    if let Some(t) = source.event_teams(key)? {
+        result.teams.append(&mut prepare_event_teams(key, &t));
+    }
+    return Ok(result);
+}
+
+/// Every official event along with every played match
+/// of those events, in the order they were played.
+/// Off-season and preseason events are included if `offseason` is set.
+fn get_matches(offseason: bool) -> Result<(Vec<Event>, Vec<Matche>)> {
+    let conn = db_connect()?;
+    let mut query = events.order(start_date).into_boxed();
+    if offseason {
+        query = query.filter(event_type.between(0..100));
+    } else {
+        query = query.filter(official.eq(1)).filter(event_type.lt(99));
+    }
+    let event_list = query.load::<Event>(&conn)?;
     let event_match_list = Matche::belonging_to(&event_list)
-        .filter(red_score.gt(-1))
-        .filter(blue_score.gt(-1))
-        .order(match_number)
-        .load::<Matche>(&conn)
-        .expect("Could not query matches")
+        .filter(status.eq(PLAYED))
+        .load::<Matche>(&conn)?
         .grouped_by(&event_list);
-    let mut final_list: Vec<Vec<Matche>> = Vec::new();
-    for mut event in event_match_list {
-        event.sort_by(|a, b| {
-            let a_level = match a.comp_level.as_ref() {
-                "qm" => 0,
-                "qf" => 1,
-                "sf" => 2,
-                "f" => 3,
-                _ => 100,
-            };
-            let b_level = match b.comp_level.as_ref() {
-                "qm" => 0,
-                "qf" => 1,
-                "sf" => 2,
-                "f" => 3,
-                _ => 100,
-            };
-            if a_level > b_level {
-                return Ordering::Greater
-            } else if a_level < b_level {
-                return Ordering::Less;
-            }
-            if a.match_number > b.match_number {
-                return Ordering::Greater;
-            } else if a.match_number < b.match_number {
-                return Ordering::Less;
-            }
-            return Ordering::Equal;
-        });
-        final_list.push(event);
+    let mut timed: Vec<(i64, Matche)> = Vec::new();
+    for (e, event) in event_list.iter().zip(event_match_list) {
+        let start = NaiveDate::parse_from_str(&e.start_date, "%Y-%m-%d")
+            .map(|d| d.and_hms(0, 0, 0).timestamp())
+            .unwrap_or(0);
+        timed.append(&mut play_order(event, start));
+    }
+    // A stable sort keeps events which started on the same day in order.
+    timed.sort_by_key(|&(at, _)| at);
+    return Ok((event_list, timed.into_iter().map(|(_, m)| m).collect()));
+}
+
+/// Put the matches of a single event in the order they were played,
+/// along with the time each started.
+///
+/// Matches are first ordered by level, then match and set number, so
+/// the first game of every playoff set comes before any second game.
+/// A match without a time is taken to start with the match before it,
+/// or at `start` if it is the first.
+fn play_order(match_list: Vec<Matche>, start: i64) -> Vec<(i64, Matche)> {
+    let mut match_list = match_list;
+    match_list.sort_by_key(|m| (level_order(&m.comp_level), m.match_number, m.set_number));
+    let mut last = start;
+    let mut timed = Vec::new();
+    for m in match_list {
+        last = m.actual_time.or(m.time).unwrap_or(last);
+        timed.push((last, m));
     }
-    return (event_list, final_list);
+    timed.sort_by_key(|&(at, _)| at);
+    return timed;
 }
 
-fn get_week_events(week_num: i32) -> Vec<Event> {
-    let conn = db_connect();
-    return events
+/// The order in which competition levels are played.
+fn level_order(level: &str) -> i32 {
+    match level {
+        "qm" => 0,
+        "ef" => 1,
+        "qf" => 2,
+        "sf" => 3,
+        "f" => 4,
+        _ => 100,
+    }
+}
+
+fn get_week_events(week_num: i32) -> Result<Vec<Event>> {
+    let conn = db_connect()?;
+    return Ok(events
         .filter(official.eq(1))
         .filter(event_type.lt(99))
         .filter(start_date.gt(&format!("{}", CURRENT_YEAR)))
         .filter(week.eq(week_num))
-        .load::<Event>(&conn).expect("Events");
+        .load::<Event>(&conn)?);
+}
+
+/// Read how new and returning teams are rated from `ELO_ROOKIE` and
+/// `ELO_DECAY`. By default rookies start at zero and teams which skip
+/// a season are only regressed like every other team.
+fn rating_policy() -> Result<(Rookie, f64)> {
+    let rookie = match env::var("ELO_ROOKIE") {
+        Ok(r) => match Rookie::parse(&r) {
+            Some(r) => r,
+            None => return Err(Error::Config(format!("Invalid ELO_ROOKIE: {}", r))),
+        },
+        Err(_) => Rookie::Fixed(0f64),
+    };
+    let decay = match env::var("ELO_DECAY") {
+        Ok(d) => match d.parse::<f64>() {
+            Ok(d) if d >= 0f64 && d <= 1f64 => d,
+            _ => return Err(Error::Config(format!("Invalid ELO_DECAY: {}", d))),
+        },
+        Err(_) => 0f64,
+    };
+    return Ok((rookie, decay));
+}
+
+/// Read how much each kind of match counts from `ELO_WEIGHTS`, such
+/// as `dcmp=1.25 qf=0.5`. Everything not given keeps its default.
+fn rating_weights() -> Result<Weights> {
+    return match env::var("ELO_WEIGHTS") {
+        Ok(w) => match Weights::parse(&w) {
+            Some(w) => Ok(w),
+            None => Err(Error::Config(format!("Invalid ELO_WEIGHTS: {}", w))),
+        },
+        Err(_) => Ok(Weights::default()),
+    };
+}
+
+/// Read the weight given to off-season matches from `--offseason`.
+/// Off-season events are not rated at all unless it is given.
+fn offseason_weight(m: &ArgMatches) -> Result<Option<f64>> {
+    return match m.value_of("offseason") {
+        Some(w) => match w.parse::<f64>() {
+            Ok(w) if w >= 0f64 && w <= 1f64 => Ok(Some(w)),
+            _ => Err(Error::Config(format!("Invalid off-season weight: {}", w))),
+        },
+        None => Ok(None),
+    };
+}
+
+/// The weight given to every match of `event`. Matches at unofficial
+/// events also count for no more than `offseason`.
+fn event_weight(event: &Event, offseason: f64, weights: &Weights) -> f64 {
+    let weight = weights.event(event.event_type, event.week);
+    return if event.official == 1 { weight } else { weight * offseason };
 }
 
-fn elo (k: f64, carry_over: f64, brier_ret: &mut f64) -> Teams {
+/// Pair every match with the season it was played in and the weight
+/// its event is given, as found by `event_weight`.
+fn with_seasons<'a>(event_list: &[Event], match_list: &'a [Matche], offseason: f64,
+                    weights: &Weights) -> Vec<(usize, f64, &'a Matche)> {
+    let seasons: HashMap<&str, (usize, f64)> = event_list.iter()
+        .map(|e| (e.id.as_str(), (e.year as usize, event_weight(e, offseason, weights))))
+        .collect();
+    return match_list.iter()
+        .map(|m| {
+            let (season, weight) = seasons[m.event_id.as_str()];
+            (season, weight, m)
+        })
+        .collect();
+}
+
+/// Ratings which have not seen any matches, set up as configured.
+fn new_teams(k: f64, carry_over: f64) -> Result<Teams> {
     let mut team_list = Teams::new(k, carry_over,FIRST_YEAR as usize);
-    let mut current_year = FIRST_YEAR;
-    let (_, event_match_list) = get_matches();
-    for event in event_match_list {
-        if event.len() < 1 {
-            continue;
-        }
-        if !event.first().unwrap().id.contains(&format!("{}", current_year)) {
-            team_list.new_year();
-            current_year += 1;
-        }
-        for m in event {
-            team_list.process_match(&m);
+    let (rookie, decay) = rating_policy()?;
+    team_list.set_policy(rookie, decay);
+    team_list.set_weights(rating_weights()?);
+    return Ok(team_list);
+}
+
+/// Rate `match_list` from the first season with ratings set up as
+/// configured, handing each match to `before` as `Teams::replay_each`
+/// does. Off-season matches are weighted by `offseason` if it is given.
+fn replay_matches<'a, F>(event_list: &[Event], match_list: &'a [Matche], offseason: Option<f64>,
+                         before: F) -> Result<Teams>
+    where F: FnMut(&mut Teams, usize, &'a Matche) {
+    let mut team_list = new_teams(K, CARRY_OVER)?;
+    let weighted = with_seasons(event_list, match_list, offseason.unwrap_or(0f64),
+                                team_list.weights());
+    team_list.replay_each(weighted, before);
+    return Ok(team_list);
+}
+
+/// Rate every match played so far. Off-season matches are included,
+/// with their results scaled by `offseason`, if it is given. These
+/// ratings are checkpointed apart from the official ones.
+fn elo (k: f64, carry_over: f64, offseason: Option<f64>, brier_ret: &mut f64) -> Result<Teams> {
+    let mut team_list = new_teams(k, carry_over)?;
+    let (event_list, match_list) = get_matches(offseason.is_some())?;
+    let (slot, params) = match offseason {
+        Some(w) => (checkpoint::WITH_OFFSEASON,
+                    format!("{} offseason={}", team_list.parameters(), w)),
+        None => (checkpoint::OFFICIAL, team_list.parameters()),
+    };
+    let weighted = with_seasons(&event_list, &match_list, offseason.unwrap_or(0f64),
+                                team_list.weights());
+    let conn = db_connect()?;
+    // Resume from the checkpoint if nothing before it has changed.
+    let mut skip = 0;
+    if let Some(saved) = checkpoint::load(&conn, slot, &params)? {
+        if checkpoint::fingerprint(&weighted, saved.match_count) == Some(saved.fingerprint) {
+            team_list = saved.teams;
+            skip = saved.match_count;
         }
     }
+    if skip < weighted.len() {
+        let print = checkpoint::fingerprint(&weighted, weighted.len()).unwrap();
+        team_list.replay(weighted.into_iter().skip(skip));
+        checkpoint::save(&conn, slot, &params, &team_list, match_list.len(), &print)?;
+    }
     let brier = team_list.brier / team_list.total as f64;
     //println!("Brier: {}", brier);
     //println!("BSS: {}", 1f64 - brier / 0.25f64);
     //println!("Predicted {} of {}, {}", team_list.wins_correct, team_list.total,
     //team_list.wins_correct as f64 / team_list.total as f64);
     *brier_ret = brier;
-    return team_list;
+    return Ok(team_list);
 }
 
 #[derive(Serialize, Clone)]
@@ -280,27 +671,45 @@ impl EventTable {
 }
 
 fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
     dotenv().ok();
     let yaml = load_yaml!("cli.yaml");
     let cli_matches = App::from_yaml(yaml).get_matches();
-    if let Some(_) = cli_matches.subcommand_matches("sync") {
-        setup();
+    if let Some(m) = cli_matches.subcommand_matches("sync") {
+        let selection = Selection::from_args(m)?;
+        let source = data_source(m, true)?;
+        for (key, e) in setup(source, selection)? {
+            println!("Could not sync {}: {}", key, e);
+        }
     }
     if let Some(m) = cli_matches.subcommand_matches("elo") {
+        let offseason = offseason_weight(m)?;
         let mut brier = 0.0f64;
-        let mut team_list = elo(15f64, 0.8f64, &mut brier);
+        let mut team_list = elo(K, CARRY_OVER, offseason, &mut brier)?;
+        let standing = team_list.standing();
+        let names = team_names()?;
         let mut teams = Vec::new();
         for (key, val) in &team_list.table {
-            if team_list.active_teams[key.replace("frc","").parse::<usize>().unwrap()] {
+            if team_list.active_teams.contains(key) {
                 teams.push(TableEntry {
                     team: key.to_owned(),
+                    nickname: nickname(&names, key),
                     rating: val.to_owned(),
+                    z: standing.z(*val),
+                    percentile: standing.percentile(*val),
                     sim: None,
                 });
             }
         }
         teams.sort_by(|x, y| y.rating.partial_cmp(&x.rating).unwrap());
         if m.is_present("html") {
+            let conn = db_connect()?;
             let tera = compile_templates!("templates/**/*");
             let mut context = Context::new();
             context.add("ratings", &teams);
@@ -310,24 +719,31 @@ fn main() {
                 Some(y) => y.parse().unwrap_or(0),
                 None => 0,
             };
-            for e in get_week_events(week_num) {
+            for e in get_week_events(week_num)? {
                 let mut event_entry = EventTable::new();
                 event_entry.key.push_str(&e.id);
                 event_entry.name.push_str(&e.name);
-                if let Some(ref sim) = simulate(&e.id) {
+                if let Some(ref sim) = simulate(&mut team_list, &e.id)? {
                     event_entry.sim = true;
                     for entry in sim {
                         event_entry.entries.push(TableEntry {
                             team: entry.key.clone(),
+                            nickname: nickname(&names, &entry.key),
                             rating: entry.elo,
+                            z: standing.z(entry.elo),
+                            percentile: standing.percentile(entry.elo),
                             sim: Some(entry.clone()),
                         });
                     }
                 } else {
-                    for team in tba::get_event_teams(&e.id).unwrap() {
+                    for team in event_teams(&conn, &e.id)? {
+                        let rating = team_list.get(&team);
                         event_entry.entries.push(TableEntry {
+                            nickname: nickname(&names, &team),
                             team: team.clone(),
-                            rating: team_list.get(&team),
+                            rating: rating,
+                            z: standing.z(rating),
+                            percentile: standing.percentile(rating),
                             sim: None,
                         });
                     }
@@ -338,58 +754,295 @@ fn main() {
                 }
             }
             context.add("events", &event_contexts);
+            let strengths = strength::event_strengths(8, offseason)?;
+            let season_strengths: Vec<&strength::EventStrength> = strengths.iter()
+                .filter(|e| e.year == CURRENT_YEAR)
+                .collect();
+            context.add("season_strengths", &season_strengths);
+            context.add("strengths", &strengths.iter().take(25).collect::<Vec<_>>());
             context.add("timestamp", &UTC::now().to_rfc2822());
             context.add("brier", &brier);
             let rendered = tera.render("index.html", &context).unwrap();
             println!("{}", rendered);
-            return;
+            return Ok(());
         } else {
             let mut i = 1;
             for t in teams {
-                println!("{:-4}. {:<8} {:<.3}", i, t.team, t.rating);
+                println!("{:-4}. {:<8} {:<8.3} {:>5.2} {:>5.1}% {}", i, t.team, t.rating, t.z,
+                         t.percentile, t.nickname);
                 i += 1;
             }
         }
     }
     if let Some(m) = cli_matches.subcommand_matches("sim") {
         let event_key = m.value_of("event").expect("Event key");
-        let teams = match simulate(event_key) {
+        let mut brier = 0.0f64;
+        let mut team_list = elo(K, CARRY_OVER, offseason_weight(m)?, &mut brier)?;
+        let teams = match simulate(&mut team_list, event_key)? {
             Some(t) => t,
             None => {
                 println!("Schedule not posted yet.");
-                return;
+                return Ok(());
+            },
+        };
+        print_forecast(&teams, &team_names()?);
+    }
+    if let Some(m) = cli_matches.subcommand_matches("season") {
+        let season_year: i32 = match m.value_of("year") {
+            Some(y) => y.parse().unwrap_or(CURRENT_YEAR),
+            None => CURRENT_YEAR,
+        };
+        let dcmp_slots: usize = m.value_of("dcmp").and_then(|x| x.parse().ok()).unwrap_or(64);
+        let cmp_slots: usize = m.value_of("cmp").and_then(|x| x.parse().ok()).unwrap_or(20);
+        for t in season::simulate_season(season_year, dcmp_slots, cmp_slots)? {
+            println!("{:8} {:<6} {:>6.1} {:>6.1} {:>6.1} {:>6.1}", t.key, t.district,
+                     t.elo, t.points, t.dcmp, t.cmp);
+        }
+    }
+    if let Some(m) = cli_matches.subcommand_matches("district") {
+        let district_key = m.value_of("district").expect("District key");
+        let mut i = 1;
+        for t in district::standings(district_key)? {
+            let event_points: Vec<String> = t.events.iter()
+                .map(|&(ref e, p)| format!("{} {:>3}", e, p)).collect();
+            println!("{:-4}. {:<8} {:>4} {}", i, t.team, t.total, event_points.join("  "));
+            i += 1;
+        }
+    }
+    if let Some(m) = cli_matches.subcommand_matches("schedule") {
+        let event_key = m.value_of("event").expect("Event key");
+        let teams = match schedule::strength_of_schedule(event_key, offseason_weight(m)?)? {
+            Some(t) => t,
+            None => {
+                println!("Schedule not posted yet.");
+                return Ok(());
             },
         };
         for t in teams {
-            println!("{:8} {:>6.1} {:>5.2} {:>5.2} {:<6} {:<6}", t.key, t.elo,
-                     t.avg,
-                     t.rank, t.tops, t.caps);
+            println!("{:8} {:>6.1} {:>7.1} {:>7.1} {:>5.2} {:>5.2} {:>+5.2}", t.key, t.elo,
+                     t.partners, t.opponents, t.expected, t.random, t.luck());
         }
     }
     if let Some(m) = cli_matches.subcommand_matches("prob") {
         let event_key = m.value_of("event").expect("Event key");
-        let conn = db_connect();
-        let match_list = matches
+        let conn = db_connect()?;
+        let match_list = Matche::scheduled()
             .filter(event_id.eq(event_key))
-            .filter(red_score.eq(-1))
-            .filter(blue_score.eq(-1))
             .order(match_number)
-            .load::<Matche>(&conn)
-            .expect("matches");
+            .load::<Matche>(&conn)?;
         let mut brier = 0.0f64;
-        let mut team_list = elo(15f64, 0.8f64, &mut brier);
-        for m in &match_list {
-            let red = team_list.sum_elo(m, true);
-            let blue = team_list.sum_elo(m, false);
-            let p = team_list.predict(m);
-            let diff = team_list.predict_diff(p);
-            let red_teams = m.get_red().join(" ");
-            let blue_teams = m.get_blue().join(" ");
-            println!("{}{:<2} [{:.0}]({:.2}) {:<23} <{:^3.0}> {:<23} ({:.2})[{:.0}]",
-                     m.comp_level, m.match_number, red, p, red_teams, diff,
-                     blue_teams, 1f64 - p, blue);
+        let mut team_list = elo(K, CARRY_OVER, offseason_weight(m)?, &mut brier)?;
+        print_probabilities(&mut team_list, &match_list);
+        let names = team_names()?;
+        let mut playing: Vec<String> = match_list.iter()
+            .flat_map(|m| m.get_red().into_iter().chain(m.get_blue().into_iter()))
+            .collect::<BTreeSet<String>>().into_iter().collect();
+        // Order by team number, keeping a team's lettered keys after it.
+        playing.sort_by_key(|t| t.trim_start_matches("frc").chars()
+                            .take_while(|c| c.is_digit(10)).collect::<String>()
+                            .parse::<usize>().unwrap_or(0));
+        if playing.iter().any(|t| names.contains_key(t)) {
+            println!();
+            for team in playing {
+                println!("{:<8} {}", team, nickname(&names, &team));
+            }
         }
     }
+    if let Some(m) = cli_matches.subcommand_matches("event") {
+        let event_key = m.value_of("event").expect("Event key");
+        let summary = match summary::summarize(event_key, offseason_weight(m)?)? {
+            Some(s) => s,
+            None => {
+                println!("No matches played yet.");
+                return Ok(());
+            },
+        };
+        let names = team_names()?;
+        println!("{} {}", event_key, summary.name);
+        let depth = |r: Option<f64>| r.map(|x| format!("{:.1}", x)).unwrap_or(String::from("-"));
+        println!("Average rating {:.1}, 8th best {}, 24th best {}", summary.average,
+                 depth(summary.eighth), depth(summary.twenty_fourth));
+        println!("Predicted {} of {} matches ({:.1}%)", summary.correct, summary.decided,
+                 summary.correct as f64 * 100f64 / summary.decided.max(1) as f64);
+        println!();
+        println!("Biggest upsets:");
+        for &(ref played, upset) in &summary.upsets {
+            let (winners, losers) = if played.red_score > played.blue_score {
+                (&played.red_teams, &played.blue_teams)
+            } else {
+                (&played.blue_teams, &played.red_teams)
+            };
+            println!("{:>4}{:<3} {:<23} beat {:<23} ({:.2})", played.comp_level,
+                     played.match_number, winners, losers, upset);
+        }
+        let shown = summary.changes.len().min(5);
+        println!();
+        println!("Biggest gains:");
+        for &(ref team, change) in summary.changes.iter().take(shown) {
+            println!("{:<8} {:>+7.1} {}", team, change, nickname(&names, team));
+        }
+        println!();
+        println!("Biggest losses:");
+        for &(ref team, change) in summary.changes.iter().rev().take(shown) {
+            println!("{:<8} {:>+7.1} {}", team, change, nickname(&names, team));
+        }
+    }
+    if let Some(m) = cli_matches.subcommand_matches("strength") {
+        let top_n: usize = m.value_of("top").and_then(|x| x.parse().ok()).unwrap_or(8);
+        let count: usize = m.value_of("count").and_then(|x| x.parse().ok()).unwrap_or(25);
+        let season_year: Option<i32> = m.value_of("year").and_then(|x| x.parse().ok());
+        let types = match m.value_of("type") {
+            Some(t) => match event_types(t) {
+                Some(types) => Some(types),
+                None => return Err(Error::Config(format!("Unknown event type: {}", t))),
+            },
+            None => None,
+        };
+        println!("{:>4}  {:<10} {:<40} {:>5} {:>7} {:>7} {:>6} {:>6}", "", "event", "", "teams",
+                 "mean", format!("top {}", top_n), "z", "top z");
+        let strengths = strength::event_strengths(top_n, offseason_weight(m)?)?;
+        let shown = strengths.iter()
+            .filter(|e| season_year.map(|y| e.year == y).unwrap_or(true))
+            .filter(|e| types.map(|t| t.contains(&e.event_type)).unwrap_or(true))
+            .take(count);
+        for (i, e) in shown.enumerate() {
+            let short: String = e.name.chars().take(40).collect();
+            println!("{:>4}. {:<10} {:<40} {:>5} {:>7.1} {:>7.1} {:>6.2} {:>6.2}", i + 1, e.key,
+                     short, e.teams, e.mean, e.top, e.mean_z, e.top_z);
+        }
+    }
+    if let Some(m) = cli_matches.subcommand_matches("compare") {
+        let first = m.value_of("first").expect("Team key");
+        let second = m.value_of("second").expect("Team key");
+        let comparison = compare::compare(first, second, offseason_weight(m)?)?;
+        let names = team_names()?;
+        println!("{} {} and {} {}", first, nickname(&names, first), second,
+                 nickname(&names, second));
+        // Wins, losses and ties of the first team as a partner and as an opponent.
+        let mut records = [(0, 0, 0), (0, 0, 0)];
+        for meeting in &comparison.meetings {
+            let record = &mut records[if meeting.partners { 0 } else { 1 }];
+            match meeting.result {
+                r if r > 0.9999 => record.0 += 1,
+                r if r < 0.0001 => record.1 += 1,
+                _ => record.2 += 1,
+            }
+        }
+        println!("Partners {}-{}-{}, {} against {} {}-{}-{}", records[0].0, records[0].1,
+                 records[0].2, first, second, records[1].0, records[1].1, records[1].2);
+        println!();
+        for meeting in &comparison.meetings {
+            let played = &meeting.played;
+            let outcome = match meeting.result {
+                r if r > 0.9999 => "won",
+                r if r < 0.0001 => "lost",
+                _ => "tied",
+            };
+            println!("{:<10} {:>3}{:<3} {:<9} {:<23} {:>4} - {:<4} {:<23} {:<4} ({:.2})",
+                     played.event_id, played.comp_level, played.match_number,
+                     if meeting.partners { "partners" } else { "opponents" },
+                     played.red_teams, played.red_score, played.blue_score, played.blue_teams,
+                     outcome, meeting.chance);
+        }
+        println!();
+        println!("{:<6} {:>8} {:>6} {:>8} {:>6}", "season", first, "z", second, "z");
+        let rating = |r: &Option<history::SeasonRating>| match *r {
+            Some(ref r) => format!("{:>8.1} {:>6.2}", r.rating, r.z),
+            None => format!("{:>8} {:>6}", "-", "-"),
+        };
+        for &(season, ref a, ref b) in &comparison.history {
+            println!("{:<6} {} {}", season, rating(a), rating(b));
+        }
+    }
+    if let Some(m) = cli_matches.subcommand_matches("history") {
+        let team = m.value_of("team").expect("Team key");
+        println!("{} {}", team, nickname(&team_names()?, team));
+        println!("{:<6} {:>8} {:>6} {:>11} {:>11}", "season", "rating", "z", "percentile", "rank");
+        for s in history::history(team, offseason_weight(m)?)? {
+            println!("{:<6} {:>8.1} {:>6.2} {:>10.1}% {:>5} of {}", s.year, s.rating, s.z,
+                     s.percentile, s.rank, s.teams);
+        }
+    }
+    if cli_matches.subcommand_matches("backtest").is_some() {
+        let outcomes = backtest::rookie_policies(K, CARRY_OVER)?;
+        print_outcomes(&outcomes);
+        println!();
+        print_outcomes(&backtest::event_weights(K, CARRY_OVER)?);
+        println!("Scored on {} matches from {}.", outcomes[0].matches, CURRENT_YEAR);
+    }
+    if let Some(m) = cli_matches.subcommand_matches("watch") {
+        let source = data_source(m, false)?;
+        let event_key = m.value_of("event").expect("Event key");
+        let interval: u64 = m.value_of("interval").and_then(|x| x.parse().ok()).unwrap_or(60);
+        watch::watch(&*source, event_key, interval, offseason_weight(m)?)?;
+    }
+    return Ok(());
+}
+
+/// Read from the fixtures given on the command line, or from The Blue
+/// Alliance if there are none, recording or replaying its responses
+/// when asked to. Requests to The Blue Alliance only use the sync
+/// history if `history` is set.
+fn data_source(m: &ArgMatches, history: bool) -> Result<Arc<dyn DataSource>> {
+    if let Some(dir) = m.value_of("fixtures") {
+        return Ok(Arc::new(FixtureSource::new(dir)));
+    }
+    let mode = if let Some(dir) = m.value_of("replay") {
+        Mode::Replay(PathBuf::from(dir))
+    } else if let Some(dir) = m.value_of("record") {
+        Mode::Record(PathBuf::from(dir))
+    } else {
+        Mode::Live
+    };
+    if history {
+        return Ok(Arc::new(TbaSource::new(open_history()?, mode)?));
+    }
+    return Ok(Arc::new(TbaSource::new(HashMap::new(), mode)?));
+}
+
+/// The event types which go by `kind`.
+fn event_types(kind: &str) -> Option<&'static [i32]> {
+    match kind {
+        "regional" => Some(&[0]),
+        "district" => Some(&[1]),
+        "dcmp" => Some(&[2, 5]),
+        "cmp" => Some(&[3, 4]),
+        "offseason" => Some(&[99, 100]),
+        _ => None,
+    }
+}
+
+/// Print how each backtest did, along with how much it improved on
+/// the Brier score of the first.
+fn print_outcomes(outcomes: &[backtest::Outcome]) {
+    println!("{:<40} {:>7} {:>11} {:>7}", "", "brier", "improvement", "correct");
+    let baseline = outcomes[0].brier;
+    for o in outcomes {
+        println!("{:<40} {:>7.4} {:>+11.4} {:>6.1}%{}", o.label, o.brier, baseline - o.brier,
+                 o.accuracy * 100f64, if o.configured { " (current)" } else { "" });
+    }
+}
+
+/// Print the win probability of each match in `match_list`.
+fn print_probabilities(team_list: &mut Teams, match_list: &[Matche]) {
+    for m in match_list {
+        let red = team_list.sum_elo(m, true);
+        let blue = team_list.sum_elo(m, false);
+        let p = team_list.predict(m);
+        let diff = team_list.predict_diff(p);
+        println!("{}{:<2} [{:.0}]({:.2}) {:<23} <{:^3.0}> {:<23} ({:.2})[{:.0}]",
+                 m.comp_level, m.match_number, red, p, m.red_teams, diff,
+                 m.blue_teams, 1f64 - p, blue);
+    }
+}
+
+/// Print forecast rankings as produced by `forecast`.
+fn print_forecast(teams: &[SimulatedResult], names: &HashMap<String, String>) {
+    for t in teams {
+        println!("{:8} {:>6.1} {:>5.2} {:>5.2} {:<6} {:<6} {}", t.key, t.elo,
+                 t.avg,
+                 t.rank, t.tops, t.caps, nickname(names, &t.key));
+    }
 }
 
 #[derive(Serialize, Clone)]
@@ -402,123 +1055,33 @@ struct SimulatedResult {
     caps: f64,
 }
 
-fn simulate(event_key: &str) -> Option<Vec<SimulatedResult>> {
-    let mut brier = 0.0f64;
-    let mut team_list = elo(15f64, 0.8f64, &mut brier);
-    let conn = db_connect();
+/// Forecast the qualification rankings of an event from `team_list`,
+/// or `None` if its schedule has not been posted.
+fn simulate(team_list: &mut Teams, event_key: &str) -> Result<Option<Vec<SimulatedResult>>> {
+    let conn = db_connect()?;
     let match_list = matches
         .filter(event_id.eq(event_key))
         .filter(comp_level.eq("qm"))
-        .load::<Matche>(&conn)
-        .expect("matches");
+        .load::<Matche>(&conn)?;
     if match_list.len() == 0 {
-        return None;
+        return Ok(None);
     }
+    let rankings = event_rankings(&conn, event_key)?;
+    return Ok(Some(forecast(team_list, &match_list, &rankings)));
+}
+
+/// Estimate the final qualification rankings of an event by
+/// simulating its remaining matches `EST_RUNS` times.
+fn forecast(team_list: &mut Teams, match_list: &[Matche],
+            rankings: &HashMap<String, TeamEventRanking>) -> Vec<SimulatedResult> {
     let mut full_rankings: HashMap<String, (usize, usize, usize, usize)> = HashMap::new();
-    let mut rankings: HashMap<String, TeamEventRanking> = HashMap::new();
-    if let Some(ranking_json) = tba::get_rankings(event_key) {
-        let rank_entries = ranking_json.rankings;
-        for entry in rank_entries {
-            rankings.insert(entry.key(), entry);
-        }
-    }
     for _ in 0..EST_RUNS {
-        let mut rankings = rankings.clone();
         let mut team_list = team_list.clone();
-        for m in &match_list {
-            let mut rng = rand::thread_rng();
-            if m.blue_score != -1 && m.red_score != -1 {
-                if rankings.len() > 0 {
-                    continue;
-                }
-                // Completed
-                for team in &m.get_red() {
-                    let ranking  = rankings.entry(team.to_owned())
-                        .or_insert(TeamEventRanking::new(team));
-                    if m.actual_r() > 0.9999 {
-                        ranking.add_win();
-                    } else if m.actual_r() < 0.49999 {
-                        ranking.add_loss();
-                    } else {
-                        ranking.add_draw();
-                    }
-                }
-                for team in &m.get_blue() {
-                    let ranking = rankings.entry(team.to_owned())
-                        .or_insert(TeamEventRanking::new(team));
-                    if m.actual_b() > 0.999 {
-                        ranking.add_win();
-                    } else if m.actual_b() < 0.49999 {
-                        ranking.add_loss();
-                    } else {
-                        ranking.add_draw();
-                    }
-                }
-            } else {
-                // simulate this.
-                let result = team_list.simulate(m);
-                let extra_prob = rng.gen::<f64>();
-                let mut red_extra_prob = 1f64;
-                let mut blue_extra_prob = 1f64;
-                for team in &m.get_red() {
-                    let ranking = rankings.entry(team.to_owned())
-                        .or_insert(TeamEventRanking::new(team));
-                    if result {
-                        ranking.add_win();
-                    } else {
-                        ranking.add_loss();
-                    }
-                    if ranking.matches_played > 3 {
-                        red_extra_prob *= 1f64 - ranking.extra_prob()
-                            * (ranking.matches_played as f64 / 4f64);
-                    }
-                }
-                for team in &m.get_blue() {
-                    let ranking = rankings.entry(team.to_owned())
-                        .or_insert(TeamEventRanking::new(team));
-                    if !result {
-                        ranking.add_win();
-                    } else {
-                        ranking.add_loss();
-                    }
-                    if ranking.matches_played > 3 {
-                        blue_extra_prob *= 1f64 - ranking.extra_prob();
-                    } else {
-                        blue_extra_prob *= 1f64 - ranking.extra_prob()
-                            * (ranking.matches_played as f64 / 4f64);
-                    }
-                }
-                if extra_prob > red_extra_prob {
-                    for team in &m.get_red() {
-                        let ranking = rankings.entry(team.to_owned())
-                            .or_insert(TeamEventRanking::new(team));
-                        ranking.add_extra();
-                    }
-                }
-                let extra_prob = rng.gen::<f64>();
-                if extra_prob > blue_extra_prob {
-                    for team in &m.get_blue() {
-                        let ranking = rankings.entry(team.to_owned())
-                            .or_insert(TeamEventRanking::new(team));
-                        ranking.add_extra();
-                    }
-                }
-            }
-        }
-        let mut teams = Vec::new();
-        for (team, val) in rankings.iter_mut() {
-            teams.push((team, val.to_usize(), val.sort_orders.get(1).unwrap_or(&0.0f64).to_owned()));
-        }
-        //teams.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap());
-        teams.sort_by(|x, y| match y.1.partial_cmp(&x.1) {
-            Some(Ordering::Less) => Ordering::Less,
-            Some(Ordering::Greater) => Ordering::Greater,
-            _ => y.2.partial_cmp(&x.2).unwrap(),
-        });
+        let teams = sim::qualification_run(&mut team_list, match_list, rankings);
         for i in 0..teams.len() {
-            let (team, ref val, _) = teams[i];
+            let (ref team, val) = teams[i];
             let entry = full_rankings.entry(team.to_owned()).or_insert((0,0,0,0));
-            entry.0 += *val;
+            entry.0 += val;
             entry.1 += i + 1;
             if i == 0 {
                 entry.2 += 1;
@@ -532,7 +1095,7 @@ fn simulate(event_key: &str) -> Option<Vec<SimulatedResult>> {
     for (team, val) in full_rankings {
         teams.push(SimulatedResult {
             key: team.clone(),
-            elo: (&mut team_list).get(&team),
+            elo: team_list.get(&team),
             avg: val.0 as f64 / EST_RUNS as f64,
             rank: val.1 as f64 / EST_RUNS as f64,
             tops: val.2 as f64 * 100f64 / EST_RUNS as f64,
@@ -545,5 +1108,313 @@ fn simulate(event_key: &str) -> Option<Vec<SimulatedResult>> {
         Some(Ordering::Greater) => Ordering::Greater,
         _ => x.rank.partial_cmp(&y.rank).unwrap(),
     });
-    return Some(teams);
+    return teams;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::Path;
+    use std::sync::MutexGuard;
+    use testing::{lock_environment, not_modified, ok, played, stand_in};
+
+    /// Point `DATABASE_URL` at a new, migrated database of its own.
+    fn test_database(label: &str) -> MutexGuard<'static, ()> {
+        let guard = lock_environment();
+        let path = env::temp_dir().join(format!("frc-elo-{}-{}.db", label, process::id()));
+        let _ = fs::remove_file(&path);
+        let url = path.to_str().unwrap().to_owned();
+        let conn = SqliteConnection::establish(&url).unwrap();
+        diesel::migrations::run_pending_migrations_in_directory(
+            &conn, Path::new("migrations"), &mut io::sink()).unwrap();
+        env::set_var("DATABASE_URL", &url);
+        return guard;
+    }
+
+    /// A new directory of recorded responses.
+    fn fixtures(label: &str) -> String {
+        let dir = env::temp_dir().join(format!("frc-elo-{}-{}", label, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        return dir.to_str().unwrap().to_owned();
+    }
+
+    /// Record `body` as the response to `url`.
+    fn record(dir: &str, url: &str, body: &str) {
+        let path = Path::new(dir).join(format!("{}.json", url));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap().write_all(body.as_bytes()).unwrap();
+    }
+
+    /// A match of `event` as The Blue Alliance lists it, starting `at`.
+    /// Unplayed matches score -1.
+    fn match_json(event: &str, level: &str, number: i32, red: &str, blue: &str,
+                  score: Option<(i32, i32)>, at: i64) -> String {
+        let keys = |teams: &str| teams.split_whitespace()
+            .map(|t| format!("\"{}\"", t)).collect::<Vec<_>>().join(",");
+        let (red_points, blue_points) = score.unwrap_or((-1, -1));
+        return format!("{{\"key\":\"{}_{}{}\",\"comp_level\":\"{}\",\"match_number\":{},\
+                        \"set_number\":1,\"event_key\":\"{}\",\"alliances\":{{\
+                        \"red\":{{\"score\":{},\"team_keys\":[{}]}},\
+                        \"blue\":{{\"score\":{},\"team_keys\":[{}]}}}},\
+                        \"time\":{},\"actual_time\":null}}",
+                       event, level, number, level, number, event, red_points, keys(red),
+                       blue_points, keys(blue), at);
+    }
+
+    /// The matches of a regional with two qualification matches and a
+    /// final, of which only the first `played` have been played.
+    fn event_matches_json(event: &str, opening: &str, played: usize) -> String {
+        let start = NaiveDate::parse_from_str(opening, "%Y-%m-%d").unwrap()
+            .and_hms(9, 0, 0).timestamp();
+        let scores = [(100, 50), (80, 90), (120, 40)];
+        let score = |i: usize| if i < played { Some(scores[i]) } else { None };
+        let match_list = vec![
+            match_json(event, "qm", 1, "frc1 frc2 frc3", "frc4 frc5 frc6", score(0), start),
+            match_json(event, "qm", 2, "frc1 frc4 frc5", "frc2 frc3 frc6", score(1),
+                       start + 600),
+            // Playoffs begin an hour after qualifications.
+            match_json(event, "f", 1, "frc1 frc2 frc3", "frc4 frc5 frc6", score(2),
+                       start + 3600),
+        ];
+        return format!("[{}]", match_list.join(","));
+    }
+
+    /// Record `event`, a regional starting on `opening`, along with
+    /// its teams and matches, of which the first `played` have been played.
+    fn record_event(dir: &str, event: &str, opening: &str, played: usize) {
+        record(dir, &format!("events/{}", &event[..4]),
+               &format!("[{{\"key\":\"{}\",\"name\":\"Test Regional\",\"event_type\":0,\
+                         \"start_date\":\"{}\",\"end_date\":null,\"week\":0,\
+                         \"district\":null,\"year\":{}}}]", event, opening, &event[..4]));
+        record(dir, &format!("event/{}/matches/simple", event),
+               &event_matches_json(event, opening, played));
+        record(dir, &format!("event/{}/teams/keys", event),
+               "[\"frc1\",\"frc2\",\"frc3\",\"frc4\",\"frc5\",\"frc6\"]");
+    }
+
+    fn select_event(key: &str) -> Selection {
+        Selection {
+            years: vec![key[..4].parse().unwrap()],
+            event: Some(key.to_owned()),
+            since: None,
+            week: None,
+        }
+    }
+
+    fn sync_args(args: &[&str]) -> Result<Selection> {
+        let yaml = load_yaml!("cli.yaml");
+        let cli_matches = App::from_yaml(yaml)
+            .get_matches_from(["frc-elo", "sync"].iter().chain(args));
+        return Selection::from_args(cli_matches.subcommand_matches("sync").unwrap());
+    }
+
+    #[test]
+    fn events_are_only_synced_from_rated_seasons() {
+        assert_eq!(sync_args(&["--event", "2017casj"]).unwrap().years, vec![2017]);
+        assert_eq!(sync_args(&["--event", "2002ca"]).unwrap().years, vec![2002]);
+        for key in &["1992cmp", "2099casj", "casj"] {
+            match sync_args(&["--event", key]) {
+                Err(Error::Config(_)) => {},
+                _ => panic!("{} was accepted", key),
+            }
+        }
+    }
+
+    #[test]
+    fn events_sync_and_rate_from_fixtures() {
+        let _database = test_database("sync");
+        let dir = fixtures("sync");
+        record_event(&dir, "2017tst", "2017-03-01", 2);
+        let failures = setup(Arc::new(FixtureSource::new(&dir)), select_event("2017tst"))
+            .unwrap();
+        assert_eq!(failures.len(), 0);
+
+        let conn = db_connect().unwrap();
+        let stored = matches.order(match_number).load::<Matche>(&conn).unwrap();
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored.iter().filter(|m| m.is_played()).count(), 2);
+        assert!(stored.iter().any(|m| m.comp_level == "f" && m.is_scheduled()));
+        assert_eq!(event_teams(&conn, "2017tst").unwrap().len(), 6);
+
+        let mut brier = 0f64;
+        let mut team_list = elo(K, CARRY_OVER, None, &mut brier).unwrap();
+        assert_eq!(team_list.total, 2);
+        assert!(team_list.get(&String::from("frc1")) > team_list.get(&String::from("frc6")));
+        assert!(team_list.active_teams.contains("frc6"));
+    }
+
+    #[test]
+    fn watching_an_event_rates_matches_as_they_finish() {
+        let _database = test_database("watch");
+        // Weights which change the event's matches must be used by both.
+        env::set_var("ELO_WEIGHTS", "regional=0.5 week=0.25");
+        let dir = fixtures("watch");
+        record_event(&dir, "2017tst", "2017-03-01", 2);
+        setup(Arc::new(FixtureSource::new(&dir)), select_event("2017tst")).unwrap();
+        let finished = fixtures("watch-finished");
+        record_event(&finished, "2017tst", "2017-03-01", 3);
+        record(&finished, "event/2017tst/rankings",
+               "{\"rankings\":[{\"rank\":1,\"matches_played\":2,\"extra_stats\":[2],\
+                \"sort_orders\":[2.0,180.0],\"record\":{\"losses\":1,\"ties\":0,\"wins\":1},\
+                \"team_key\":\"frc1\"}]}");
+        let mut watched = watch::watch(&FixtureSource::new(&finished), "2017tst", 0, None)
+            .unwrap();
+
+        let conn = db_connect().unwrap();
+        let stored = matches.filter(comp_level.eq("f")).first::<Matche>(&conn).unwrap();
+        assert!(stored.is_played());
+        assert_eq!((stored.red_score, stored.blue_score), (120, 40));
+        assert_eq!(event_rankings(&conn, "2017tst").unwrap()["frc1"].matches_played, 2);
+        let mut brier = 0f64;
+        let mut replayed = elo(K, CARRY_OVER, None, &mut brier).unwrap();
+        for team in &["frc1", "frc4"] {
+            let team = team.to_string();
+            assert!((watched.get(&team) - replayed.get(&team)).abs() < 1e-9);
+        }
+        env::remove_var("ELO_WEIGHTS");
+    }
+
+    #[test]
+    fn watching_an_event_only_reads_matches_which_have_changed() {
+        let _database = test_database("watch-polling");
+        let dir = fixtures("watch-polling");
+        record_event(&dir, "2017tst", "2017-03-01", 2);
+        setup(Arc::new(FixtureSource::new(&dir)), select_event("2017tst")).unwrap();
+        let modified = "Last-Modified: Wed, 01 Mar 2017 12:00:00 GMT";
+        let (url, requests) = stand_in(vec![
+            ok(&[modified], &event_matches_json("2017tst", "2017-03-01", 2)),
+            ok(&[], "null"),
+            not_modified(),
+            ok(&[], &event_matches_json("2017tst", "2017-03-01", 3)),
+            ok(&[], "null"),
+        ]);
+        env::set_var("TBA_URL", &url);
+        env::set_var("TBA_KEY", "test");
+        let source = TbaSource::new(HashMap::new(), Mode::Live).unwrap();
+        let watched = watch::watch(&source, "2017tst", 0, None);
+        env::remove_var("TBA_URL");
+        env::remove_var("TBA_KEY");
+        watched.unwrap();
+
+        let requests = requests.join().unwrap();
+        assert!(requests[0].starts_with("GET /api/v3/event/2017tst/matches/simple "));
+        assert!(!requests[0].contains("If-Modified-Since"));
+        assert!(requests[1].starts_with("GET /api/v3/event/2017tst/rankings "));
+        // The next poll only asks for matches changed since the last.
+        assert!(requests[2].starts_with("GET /api/v3/event/2017tst/matches/simple "));
+        assert!(requests[2].contains("If-Modified-Since: Wed, 01 Mar 2017 12:00:00 GMT"));
+        let conn = db_connect().unwrap();
+        let stored = matches.filter(comp_level.eq("f")).first::<Matche>(&conn).unwrap();
+        assert_eq!((stored.red_score, stored.blue_score), (120, 40));
+    }
+
+    #[test]
+    fn watching_the_first_event_of_a_season_starts_the_season() {
+        let _database = test_database("watch-season");
+        let last_season = fixtures("watch-season-2016");
+        record_event(&last_season, "2016tst", "2016-03-01", 3);
+        setup(Arc::new(FixtureSource::new(&last_season)), select_event("2016tst")).unwrap();
+        let dir = fixtures("watch-season");
+        record_event(&dir, "2017tst", "2017-03-01", 0);
+        setup(Arc::new(FixtureSource::new(&dir)), select_event("2017tst")).unwrap();
+        let finished = fixtures("watch-season-finished");
+        record_event(&finished, "2017tst", "2017-03-01", 3);
+        let mut watched = watch::watch(&FixtureSource::new(&finished), "2017tst", 0, None)
+            .unwrap();
+
+        let mut brier = 0f64;
+        let mut replayed = elo(K, CARRY_OVER, None, &mut brier).unwrap();
+        assert!(watched.active_teams.contains("frc1"));
+        for team in &["frc1", "frc4"] {
+            let team = team.to_string();
+            assert!((watched.get(&team) - replayed.get(&team)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn matches_are_rated_in_the_season_of_their_event() {
+        let _database = test_database("seasons");
+        let dir = fixtures("seasons");
+        record_event(&dir, "2016tst", "2016-03-01", 3);
+        // Listed as a 2017 event, though it started before the new year.
+        record_event(&dir, "2017tst", "2016-12-31", 3);
+        for key in &["2016tst", "2017tst"] {
+            setup(Arc::new(FixtureSource::new(&dir)), select_event(key)).unwrap();
+        }
+
+        let (event_list, match_list) = get_matches(false).unwrap();
+        let weights = rating_weights().unwrap();
+        let seasons: Vec<(usize, &str)> = with_seasons(&event_list, &match_list, 0f64, &weights)
+            .into_iter().map(|(season, _, m)| (season, m.event_id.as_str())).collect();
+        assert_eq!(seasons, vec![(2016, "2016tst"), (2016, "2016tst"), (2016, "2016tst"),
+                                 (2017, "2017tst"), (2017, "2017tst"), (2017, "2017tst")]);
+        let team_list = replay_matches(&event_list, &match_list, None, |_, _, _| {}).unwrap();
+        // Only matches rated in the current season make a team active.
+        assert!(team_list.active_teams.contains("frc1"));
+    }
+
+    fn playoff(level: &str, set: i32, number: i32, at: Option<i64>) -> Matche {
+        let mut m = played("2017tst", number, &[], &[], 0, 0);
+        m.id = format!("2017tst_{}{}m{}", level, set, number);
+        m.comp_level = level.to_owned();
+        m.set_number = set;
+        m.time = at;
+        return m;
+    }
+
+    fn order(match_list: Vec<Matche>) -> Vec<String> {
+        return play_order(match_list, 0).into_iter().map(|(_, m)| m.id).collect();
+    }
+
+    #[test]
+    fn untimed_playoff_sets_are_interleaved() {
+        let match_list = vec![playoff("f", 1, 1, None), playoff("qf", 1, 1, None),
+                              playoff("qf", 1, 2, None), playoff("qf", 2, 1, None),
+                              playoff("qf", 2, 2, None), playoff("ef", 1, 1, None)];
+        assert_eq!(order(match_list), vec!["2017tst_ef1m1", "2017tst_qf1m1", "2017tst_qf2m1",
+                                           "2017tst_qf1m2", "2017tst_qf2m2", "2017tst_f1m1"]);
+    }
+
+    #[test]
+    fn playoff_matches_follow_their_times() {
+        // The second game of the first set was replayed after a field fault.
+        let match_list = vec![playoff("qf", 1, 1, Some(100)), playoff("qf", 1, 2, Some(400)),
+                              playoff("qf", 2, 1, Some(200)), playoff("qf", 2, 2, Some(300))];
+        assert_eq!(order(match_list), vec!["2017tst_qf1m1", "2017tst_qf2m1", "2017tst_qf2m2",
+                                           "2017tst_qf1m2"]);
+    }
+
+    #[test]
+    fn preseason_matches_count_as_off_season() {
+        let preseason = EventJSON {
+            key: String::from("2017pre"),
+            name: String::from("Preseason Scrimmage"),
+            event_type: 100,
+            start_date: String::from("2017-02-18"),
+            end_date: None,
+            week: None,
+            district: None,
+            year: Some(2017),
+        };
+        let prepared = prepare_event(&preseason);
+        assert_eq!(prepared.official, 0);
+        let event_list = vec![Event {
+            id: prepared.id.to_owned(),
+            name: prepared.name.to_owned(),
+            event_type: prepared.event_type,
+            official: prepared.official,
+            start_date: prepared.start_date.to_owned(),
+            week: prepared.week,
+            district: None,
+            year: prepared.year,
+        }];
+        let mut m = playoff("qm", 1, 1, None);
+        m.event_id = String::from("2017pre");
+        let match_list = vec![m];
+        let weights = Weights::default();
+        let weighted = with_seasons(&event_list, &match_list, 0.25f64, &weights);
+        assert_eq!(weighted[0].1, 0.25f64 * weights.event(100, prepared.week));
+    }
 }
@@ -1,4 +1,13 @@
 use super::schema::*;
+use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
+
+/// A match which has not been played yet.
+pub const SCHEDULED: &'static str = "scheduled";
+/// A match with a final score.
+pub const PLAYED: &'static str = "played";
+/// A playoff match which was never needed because its series was decided.
+pub const CANCELLED: &'static str = "cancelled";
 
 #[derive(Deserialize, Queryable, Debug, Clone)]
 pub struct EventJSON {
@@ -7,7 +16,15 @@ pub struct EventJSON {
     pub event_type: usize,
     //pub official: Option<bool>,
     pub start_date: String,
+    pub end_date: Option<String>,
     pub week: Option<i32>,
+    pub district: Option<DistrictJSON>,
+    pub year: Option<i32>,
+}
+
+#[derive(Deserialize, Queryable, Debug, Clone)]
+pub struct DistrictJSON {
+    pub key: String,
 }
 
 #[derive(Queryable, Identifiable, Associations)]
@@ -19,6 +36,8 @@ pub struct Event {
     pub official: i32,
     pub start_date: String,
     pub week: i32,
+    pub district: Option<String>,
+    pub year: i32,
 }
 
 #[derive(Insertable)]
@@ -30,6 +49,8 @@ pub struct NewEvent<'a> {
     pub official: i32,
     pub start_date: &'a str,
     pub week: i32,
+    pub district: Option<&'a str>,
+    pub year: i32,
 }
 
 pub fn prepare_event(event: &EventJSON) -> NewEvent {
@@ -37,15 +58,25 @@ pub fn prepare_event(event: &EventJSON) -> NewEvent {
         id: &event.key,
         name: &event.name,
         event_type: event.event_type as i32,
+        // Off-season, preseason and any other events outside the
+        // regular competition season are unofficial.
         official: match event.event_type {
-            99 => 0,
-            _ => 1,
+            0..=5 => 1,
+            _ => 0,
         },
         week: match event.week {
             Some(w) => w,
             None => 7,
         },
         start_date: &event.start_date,
+        district: match event.district {
+            Some(ref d) => Some(&d.key),
+            None => None,
+        },
+        year: match event.year {
+            Some(y) => y,
+            None => event.start_date[..4].parse().unwrap_or(0),
+        },
     }
 }
 
@@ -69,6 +100,10 @@ pub struct GameMatch {
     pub set_number: i32,
     pub event_key: String,
     pub alliances: Alliances,
+    /// Scheduled start as a Unix timestamp.
+    pub time: Option<i64>,
+    /// Actual start as a Unix timestamp.
+    pub actual_time: Option<i64>,
 }
 
 #[derive(Debug, Queryable, Identifiable, Associations, Clone)]
@@ -81,29 +116,44 @@ pub struct Matche {
     pub event_id: String,
     pub red_score: i32,
     pub blue_score: i32,
-    pub red1: String,
-    pub red2: String,
-    pub red3: Option<String>,
-    pub blue1: String,
-    pub blue2: String,
-    pub blue3: Option<String>,
+    /// The keys of the red teams, separated by spaces.
+    pub red_teams: String,
+    /// The keys of the blue teams, separated by spaces.
+    pub blue_teams: String,
+    pub status: String,
+    pub time: Option<i64>,
+    pub actual_time: Option<i64>,
 }
 
 impl Matche {
+    /// Every played match.
+    pub fn played<'a>() -> matches::BoxedQuery<'a, Sqlite> {
+        return matches::table.filter(matches::status.eq(PLAYED)).into_boxed();
+    }
+
+    /// Every match still waiting to be played.
+    pub fn scheduled<'a>() -> matches::BoxedQuery<'a, Sqlite> {
+        return matches::table.filter(matches::status.eq(SCHEDULED)).into_boxed();
+    }
+
+    pub fn is_played(&self) -> bool {
+        return self.status == PLAYED;
+    }
+
+    pub fn is_scheduled(&self) -> bool {
+        return self.status == SCHEDULED;
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.status == CANCELLED;
+    }
+
     pub fn get_red(&self) -> Vec<String> {
-        let mut teams = vec!(self.red1.clone(), self.red2.clone());
-        if let Some(ref red3) = self.red3 {
-            teams.push(red3.to_owned());
-        }
-        return teams;
+        return self.red_teams.split_whitespace().map(|x| x.to_owned()).collect();
     }
 
     pub fn get_blue(&self) -> Vec<String> {
-        let mut teams = vec!(self.blue1.clone(), self.blue2.clone());
-        if let Some(ref blue3) = self.blue3 {
-            teams.push(blue3.to_owned());
-        }
-        return teams;
+        return self.blue_teams.split_whitespace().map(|x| x.to_owned()).collect();
     }
 
     pub fn actual_r(&self) -> f64 {
@@ -139,17 +189,41 @@ pub struct NewMatch<'a> {
     pub event_id: &'a str,
     pub red_score: i32,
     pub blue_score: i32,
-    pub red1: &'a str,
-    pub red2: &'a str,
-    pub red3: Option<&'a str>,
-    pub blue1: &'a str,
-    pub blue2: &'a str,
-    pub blue3: Option<&'a str>,
+    pub red_teams: String,
+    pub blue_teams: String,
+    pub status: &'static str,
+    pub time: Option<i64>,
+    pub actual_time: Option<i64>,
 }
 
+impl<'a> NewMatch<'a> {
+    /// The match as it will be loaded once stored.
+    pub fn to_match(&self) -> Matche {
+        Matche {
+            id: self.id.to_owned(),
+            comp_level: self.comp_level.to_owned(),
+            match_number: self.match_number,
+            set_number: self.set_number,
+            event_id: self.event_id.to_owned(),
+            red_score: self.red_score,
+            blue_score: self.blue_score,
+            red_teams: self.red_teams.clone(),
+            blue_teams: self.blue_teams.clone(),
+            status: self.status.to_owned(),
+            time: self.time,
+            actual_time: self.actual_time,
+        }
+    }
+}
 
-
+/// Prepare a match for insertion. Alliances may have any number of
+/// teams, but a match without a team on each side is left out.
 pub fn prepare_match(game_match: &GameMatch) -> Option<NewMatch> {
+    if game_match.alliances.red.team_keys.len() == 0 ||
+        game_match.alliances.blue.team_keys.len() == 0 {
+        return None;
+    }
+    let played = game_match.alliances.red.score > -1 && game_match.alliances.blue.score > -1;
     Some(NewMatch {
         id: &game_match.key,
         comp_level: &game_match.comp_level,
@@ -158,38 +232,282 @@ pub fn prepare_match(game_match: &GameMatch) -> Option<NewMatch> {
         event_id: &game_match.event_key,
         red_score: game_match.alliances.red.score,
         blue_score: game_match.alliances.blue.score,
-        red1: match game_match.alliances.red.team_keys.get(0) {
-            Some(i) => i,
-            None => return None,
-        },
-        red2: match game_match.alliances.red.team_keys.get(1) {
-            Some(i) => i,
-            None => return None,
-        },
-        red3: match game_match.alliances.red.team_keys.get(2) {
-            Some(i) => Some(i),
-            None => None,
-        },
-        blue1: match game_match.alliances.blue.team_keys.get(0) {
-            Some(i) => i,
-            None => return None,
-        },
-        blue2: match game_match.alliances.blue.team_keys.get(1) {
-            Some(i) => i,
-            None => return None,
-        },
-        blue3: match game_match.alliances.blue.team_keys.get(2) {
-            Some(i) => Some(i),
-            None => None,
-        },
+        red_teams: game_match.alliances.red.team_keys.join(" "),
+        blue_teams: game_match.alliances.blue.team_keys.join(" "),
+        status: if played { PLAYED } else { SCHEDULED },
+        time: game_match.time,
+        actual_time: game_match.actual_time,
     })
 }
 
-/*
-#[derive(Deserialize, Queryable)]
-pub struct Team {
-team_number: u16,
-nickname: String,
-key: String,
+/// Prepare every match of an event. Playoff matches left unplayed
+/// after one alliance has already won their series are cancelled.
+pub fn prepare_matches(game_matches: &[GameMatch]) -> Vec<NewMatch> {
+    let mut new_matches: Vec<NewMatch> = game_matches.iter()
+        .filter_map(|x| prepare_match(x)).collect();
+    let mut wins: Vec<(String, i32, i32, i32)> = Vec::new();
+    for m in new_matches.iter().filter(|m| m.comp_level != "qm" && m.status == PLAYED) {
+        let index = match wins.iter().position(|w| w.0 == m.comp_level && w.1 == m.set_number) {
+            Some(i) => i,
+            None => {
+                wins.push((m.comp_level.to_owned(), m.set_number, 0, 0));
+                wins.len() - 1
+            },
+        };
+        if m.red_score > m.blue_score {
+            wins[index].2 += 1;
+        } else if m.blue_score > m.red_score {
+            wins[index].3 += 1;
+        }
+    }
+    for m in new_matches.iter_mut().filter(|m| m.status == SCHEDULED) {
+        if wins.iter().any(|w| w.0 == m.comp_level && w.1 == m.set_number && (w.2 >= 2 || w.3 >= 2)) {
+            m.status = CANCELLED;
+        }
+    }
+    return new_matches;
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DistrictListJSON {
+    pub abbreviation: String,
+    pub display_name: String,
+    pub key: String,
+    pub year: i32,
+}
+
+#[derive(Insertable)]
+#[table_name="districts"]
+pub struct NewDistrict<'a> {
+    pub id: &'a str,
+    pub abbreviation: &'a str,
+    pub name: &'a str,
+    pub year: i32,
+}
+
+pub fn prepare_district(district: &DistrictListJSON) -> NewDistrict {
+    NewDistrict {
+        id: &district.key,
+        abbreviation: &district.abbreviation,
+        name: &district.display_name,
+        year: district.year,
+    }
+}
+
+#[derive(Insertable)]
+#[table_name="district_teams"]
+pub struct NewDistrictTeam {
+    pub id: String,
+    pub district_id: String,
+    pub team_key: String,
+}
+
+pub fn prepare_district_team(district: &str, team: &str) -> NewDistrictTeam {
+    NewDistrictTeam {
+        id: format!("{}_{}", district, team),
+        district_id: district.to_owned(),
+        team_key: team.to_owned(),
+    }
+}
+
+/// A team's ranking at an event, loaded from the columns after `event_id`.
+#[derive(Queryable, Debug, Clone)]
+pub struct Ranking {
+    pub team_key: String,
+    pub rank: i32,
+    pub wins: i32,
+    pub losses: i32,
+    pub ties: i32,
+    pub matches_played: i32,
+    pub ranking_score: i32,
+    pub tiebreaker: f64,
+}
+
+#[derive(Insertable)]
+#[table_name="rankings"]
+pub struct NewRanking {
+    pub id: String,
+    pub event_id: String,
+    pub team_key: String,
+    pub rank: i32,
+    pub wins: i32,
+    pub losses: i32,
+    pub ties: i32,
+    pub matches_played: i32,
+    pub ranking_score: i32,
+    pub tiebreaker: f64,
+}
+
+#[derive(Insertable)]
+#[table_name="event_teams"]
+pub struct NewEventTeam {
+    pub id: String,
+    pub event_id: String,
+    pub team_key: String,
+}
+
+pub fn prepare_event_teams(event: &str, teams: &[String]) -> Vec<NewEventTeam> {
+    teams.iter().map(|team| NewEventTeam {
+        id: format!("{}_{}", event, team),
+        event_id: event.to_owned(),
+        team_key: team.to_owned(),
+    }).collect()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AllianceJSON {
+    pub picks: Vec<String>,
+}
+
+/// A member of an alliance, loaded from the columns after `event_id`.
+#[derive(Queryable, Debug, Clone)]
+pub struct AllianceTeam {
+    pub alliance: i32,
+    pub pick: i32,
+    pub team_key: String,
+}
+
+#[derive(Insertable)]
+#[table_name="alliance_teams"]
+pub struct NewAllianceTeam {
+    pub id: String,
+    pub event_id: String,
+    pub alliance: i32,
+    pub pick: i32,
+    pub team_key: String,
+}
+
+/// Flatten the alliances of an event into one row per team.
+/// Alliances and picks both start at 0.
+pub fn prepare_alliances(event: &str, alliances: &[AllianceJSON]) -> Vec<NewAllianceTeam> {
+    let mut teams = Vec::new();
+    for (i, alliance) in alliances.iter().enumerate() {
+        for (j, team) in alliance.picks.iter().enumerate() {
+            teams.push(NewAllianceTeam {
+                id: format!("{}_{}_{}", event, i, j),
+                event_id: event.to_owned(),
+                alliance: i as i32,
+                pick: j as i32,
+                team_key: team.to_owned(),
+            });
+        }
+    }
+    return teams;
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AwardRecipientJSON {
+    pub team_key: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AwardJSON {
+    pub award_type: i32,
+    pub event_key: String,
+    pub recipient_list: Vec<AwardRecipientJSON>,
+}
+
+/// An award given to a team, loaded from the columns after `event_id`.
+#[derive(Queryable, Debug, Clone)]
+pub struct Award {
+    pub award_type: i32,
+    pub team_key: String,
+}
+
+#[derive(Insertable)]
+#[table_name="awards"]
+pub struct NewAward {
+    pub id: String,
+    pub event_id: String,
+    pub award_type: i32,
+    pub team_key: String,
+}
+
+/// One row per team receiving an award. Awards given to
+/// individuals rather than teams are skipped.
+pub fn prepare_awards(awards: &[AwardJSON]) -> Vec<NewAward> {
+    let mut rows = Vec::new();
+    for award in awards {
+        for recipient in &award.recipient_list {
+            if let Some(ref team) = recipient.team_key {
+                rows.push(NewAward {
+                    id: format!("{}_{}_{}", award.event_key, award.award_type, team),
+                    event_id: award.event_key.clone(),
+                    award_type: award.award_type,
+                    team_key: team.clone(),
+                });
+            }
+        }
+    }
+    return rows;
+}
+
+/// Saved ratings, loaded from the columns after `id`.
+#[derive(Queryable, Debug, Clone)]
+pub struct Checkpoint {
+    pub parameters: String,
+    pub match_count: i32,
+    pub fingerprint: String,
+    pub ratings: String,
+}
+
+#[derive(Insertable)]
+#[table_name="checkpoints"]
+pub struct NewCheckpoint<'a> {
+    pub id: i32,
+    pub parameters: &'a str,
+    pub match_count: i32,
+    pub fingerprint: &'a str,
+    pub ratings: &'a str,
+}
+
+/// What was last read of a URL during sync.
+#[derive(Queryable, Debug, Clone)]
+pub struct SyncState {
+    pub url: String,
+    pub last_modified: String,
+    pub etag: String,
+}
+
+#[derive(Insertable)]
+#[table_name="sync_state"]
+pub struct NewSyncState {
+    pub url: String,
+    pub last_modified: String,
+    pub etag: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct TeamJSON {
+    pub key: String,
+    pub team_number: i32,
+    pub nickname: Option<String>,
+    pub city: Option<String>,
+    pub state_prov: Option<String>,
+    pub country: Option<String>,
+    pub rookie_year: Option<i32>,
+}
+
+#[derive(Insertable)]
+#[table_name="teams"]
+pub struct NewTeam<'a> {
+    pub key: &'a str,
+    pub team_number: i32,
+    pub nickname: Option<&'a str>,
+    pub city: Option<&'a str>,
+    pub state_prov: Option<&'a str>,
+    pub country: Option<&'a str>,
+    pub rookie_year: Option<i32>,
+}
+
+pub fn prepare_team(team: &TeamJSON) -> NewTeam {
+    NewTeam {
+        key: &team.key,
+        team_number: team.team_number,
+        nickname: team.nickname.as_ref().map(|x| x.as_str()),
+        city: team.city.as_ref().map(|x| x.as_str()),
+        state_prov: team.state_prov.as_ref().map(|x| x.as_str()),
+        country: team.country.as_ref().map(|x| x.as_str()),
+        rookie_year: team.rookie_year,
+    }
 }
-*/
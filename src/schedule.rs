@@ -0,0 +1,110 @@
+use super::models::{Matche, SCHEDULED};
+use super::{db_connect, elo, sim, K, CARRY_OVER};
+use error::Result;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+/// The number of simulations run for both the posted
+/// schedule and the random schedules.
+const SCHEDULE_RUNS: usize = 1000;
+
+#[derive(Serialize, Clone)]
+pub struct ScheduleStrength {
+    pub key: String,
+    pub elo: f64,
+    /// Average combined rating of a team's partners.
+    pub partners: f64,
+    /// Average combined rating of a team's opponents.
+    pub opponents: f64,
+    /// Expected ranking points under the posted schedule.
+    pub expected: f64,
+    /// Expected ranking points under a random schedule, over as many
+    /// matches as the team plays on the posted schedule.
+    pub random: f64,
+}
+
+impl ScheduleStrength {
+    /// Ranking points gained (or lost when negative) from the draw.
+    pub fn luck(&self) -> f64 {
+        return self.expected - self.random;
+    }
+}
+
+/// Compare every team's qualification schedule at an event against
+/// random schedules with the same number of matches. Teams may play
+/// a different number of matches in each, so ranking points are
+/// compared per match played.
+pub fn strength_of_schedule(event_key: &str,
+                            offseason: Option<f64>) -> Result<Option<Vec<ScheduleStrength>>> {
+    use schema::matches::dsl::*;
+    let mut brier = 0.0f64;
+    let mut team_list = elo(K, CARRY_OVER, offseason, &mut brier)?;
+    let conn = db_connect()?;
+    let mut match_list = matches
+        .filter(event_id.eq(event_key))
+        .filter(comp_level.eq("qm"))
+        .order(match_number)
+        .load::<Matche>(&conn)?;
+    if match_list.len() == 0 {
+        return Ok(None);
+    }
+    // Judge the draw itself, not the results which followed it.
+    for m in match_list.iter_mut() {
+        m.status = String::from(SCHEDULED);
+    }
+    let mut sums: HashMap<String, (f64, f64, usize)> = HashMap::new();
+    for m in &match_list {
+        let red = team_list.sum_elo(m, true);
+        let blue = team_list.sum_elo(m, false);
+        for team in m.get_red() {
+            let partners = red - team_list.get(&team);
+            let entry = sums.entry(team).or_insert((0f64, 0f64, 0));
+            entry.0 += partners;
+            entry.1 += blue;
+            entry.2 += 1;
+        }
+        for team in m.get_blue() {
+            let partners = blue - team_list.get(&team);
+            let entry = sums.entry(team).or_insert((0f64, 0f64, 0));
+            entry.0 += partners;
+            entry.1 += red;
+            entry.2 += 1;
+        }
+    }
+    let team_keys: Vec<String> = sums.keys().cloned().collect();
+    let mut expected: HashMap<String, usize> = HashMap::new();
+    // Ranking points and matches played under random schedules.
+    let mut random: HashMap<String, (usize, usize)> = HashMap::new();
+    for _ in 0..SCHEDULE_RUNS {
+        let mut posted = team_list.clone();
+        for (team, val) in sim::qualification_run(&mut posted, &match_list, &HashMap::new()) {
+            *expected.entry(team).or_insert(0) += val;
+        }
+        let schedule = sim::random_matches(event_key, &team_keys, match_list.len());
+        for m in &schedule {
+            for team in m.get_red().into_iter().chain(m.get_blue()) {
+                random.entry(team).or_insert((0, 0)).1 += 1;
+            }
+        }
+        let mut drawn = team_list.clone();
+        for (team, val) in sim::qualification_run(&mut drawn, &schedule, &HashMap::new()) {
+            random.entry(team).or_insert((0, 0)).0 += val;
+        }
+    }
+    let mut teams = Vec::new();
+    for (team, (partners, opponents, played)) in sums {
+        // Scale the random schedules to the matches played on the posted one.
+        let (points, drawn) = random.get(&team).cloned().unwrap_or((0, 0));
+        let per_match = points as f64 / drawn.max(1) as f64;
+        teams.push(ScheduleStrength {
+            elo: team_list.get(&team),
+            partners: partners / played as f64,
+            opponents: opponents / played as f64,
+            expected: *expected.get(&team).unwrap_or(&0) as f64 / SCHEDULE_RUNS as f64,
+            random: per_match * played as f64,
+            key: team,
+        });
+    }
+    teams.sort_by(|x, y| y.luck().partial_cmp(&x.luck()).unwrap());
+    return Ok(Some(teams));
+}
@@ -0,0 +1,266 @@
+use super::models::{Event, Matche};
+use super::elo::Teams;
+use super::{db_connect, elo, event_teams, sim, district, K, CARRY_OVER};
+use error::Result;
+use diesel::prelude::*;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use rand::{self, Rng};
+
+/// The number of seasons to run when modeling.
+const SEASON_RUNS: usize = 1000;
+/// Qualification matches played by each team when
+/// a schedule has to be generated.
+const SCHEDULE_ROUNDS: usize = 12;
+
+#[derive(Serialize, Clone)]
+pub struct SeasonResult {
+    pub key: String,
+    pub district: String,
+    pub elo: f64,
+    pub points: f64,
+    pub dcmp: f64,
+    pub cmp: f64,
+}
+
+/// An event along with everything needed to simulate it.
+struct SeasonEvent {
+    key: String,
+    district: String,
+    championship: bool,
+    /// A division of a district championship.
+    division: bool,
+    teams: Vec<String>,
+    schedule: Vec<Matche>,
+    /// Official points for events which have already finished.
+    points: Option<HashMap<String, i32>>,
+}
+
+/// Load the district events of a season. Events without a posted
+/// schedule use the team list stored during sync instead.
+fn load_events(year: i32) -> Result<Vec<SeasonEvent>> {
+    use schema::events::dsl as ev;
+    use schema::matches::dsl::comp_level;
+    let conn = db_connect()?;
+    let event_list = ev::events
+        .filter(ev::official.eq(1))
+        .filter(ev::district.is_not_null())
+        .filter(ev::event_type.eq_any(vec![1, 2, 5]))
+        .filter(ev::year.eq(year))
+        .order(ev::start_date)
+        .load::<Event>(&conn)?;
+    let match_list = Matche::belonging_to(&event_list)
+        .filter(comp_level.eq("qm"))
+        .load::<Matche>(&conn)?
+        .grouped_by(&event_list);
+    let mut season = Vec::new();
+    for (e, schedule) in event_list.into_iter().zip(match_list) {
+        let mut teams: Vec<String> = Vec::new();
+        for m in &schedule {
+            for team in m.get_red().into_iter().chain(m.get_blue()) {
+                if !teams.contains(&team) {
+                    teams.push(team);
+                }
+            }
+        }
+        let championship = e.event_type == 2 || e.event_type == 5;
+        let mut points = None;
+        if district::event_complete(&conn, &e)? {
+            points = Some(district::event_points(&conn, &e)?);
+        }
+        // District championship divisions have their teams assigned in advance.
+        if teams.len() == 0 && (!championship || e.event_type == 5) {
+            teams = event_teams(&conn, &e.id)?;
+        }
+        season.push(SeasonEvent {
+            key: e.id,
+            district: e.district.unwrap_or(String::new()),
+            championship: championship,
+            division: e.event_type == 5,
+            teams: teams,
+            schedule: schedule,
+            points: points,
+        });
+    }
+    return Ok(season);
+}
+
+/// Load the district each team belongs to in a season.
+fn load_members(year: i32) -> Result<HashMap<String, String>> {
+    use schema::districts::dsl as d;
+    use schema::district_teams::dsl as t;
+    let conn = db_connect()?;
+    let district_keys = d::districts
+        .select(d::id)
+        .filter(d::year.eq(year))
+        .load::<String>(&conn)?;
+    let mut members = HashMap::new();
+    for key in district_keys {
+        let teams = t::district_teams
+            .select(t::team_key)
+            .filter(t::district_id.eq(&key))
+            .load::<String>(&conn)?;
+        for team in teams {
+            members.insert(team, key.clone());
+        }
+    }
+    return Ok(members);
+}
+
+/// Simulate a single event and return the district points earned.
+/// Events which have already finished return their official points.
+fn event_points(team_list: &mut Teams, year: i32, event: &SeasonEvent,
+                teams: &[String]) -> HashMap<String, i32> {
+    if let Some(ref points) = event.points {
+        return points.clone();
+    }
+    let mut points = HashMap::new();
+    let schedule = match event.schedule.len() {
+        0 => sim::random_schedule(&event.key, teams, SCHEDULE_ROUNDS),
+        _ => event.schedule.clone(),
+    };
+    let results = sim::qualification_run(team_list, &schedule, &HashMap::new());
+    for (i, &(ref team, val)) in results.iter().enumerate() {
+        let wins = val as i32 / 2;
+        let ties = val as i32 % 2;
+        points.insert(team.to_owned(),
+                      district::qualification_points(year, i + 1, results.len(), wins, ties));
+    }
+    let ranked: Vec<String> = results.into_iter().map(|(team, _)| team).collect();
+    let alliances = sim::select_alliances(team_list, &ranked);
+    for (i, alliance) in alliances.iter().enumerate() {
+        for (pick, team) in alliance.iter().enumerate() {
+            *points.entry(team.to_owned()).or_insert(0) += district::alliance_points(i, pick);
+        }
+    }
+    let (wins, _) = sim::playoff_run(team_list, &event.key, &alliances);
+    for (i, alliance) in alliances.iter().enumerate() {
+        for team in alliance {
+            *points.entry(team.to_owned()).or_insert(0) += wins[i] as i32 * district::PLAYOFF_WIN;
+        }
+    }
+    // Awards are not modeled by ratings, so they are handed out at random.
+    let mut rng = rand::thread_rng();
+    let mut winners = ranked.clone();
+    rng.shuffle(&mut winners);
+    let mut awards = vec![district::CHAIRMANS, district::ENGINEERING_INSPIRATION];
+    awards.append(&mut vec![district::JUDGED; district::JUDGED_AWARDS]);
+    for (team, award) in winners.iter().zip(awards) {
+        *points.entry(team.to_owned()).or_insert(0) += award;
+    }
+    if event.championship {
+        for (_, val) in points.iter_mut() {
+            *val *= district::DCMP_MULTIPLIER;
+        }
+    }
+    return points;
+}
+
+/// Rank the members of a district by their points.
+fn standings(points: &HashMap<String, i32>, members: &HashMap<String, String>,
+             district_key: &str) -> Vec<String> {
+    let mut teams: Vec<(&String, i32)> = members.iter()
+        .filter(|&(_, d)| d == district_key)
+        .map(|(team, _)| (team, *points.get(team).unwrap_or(&0)))
+        .collect();
+    teams.sort_by(|x, y| y.1.cmp(&x.1));
+    return teams.into_iter().map(|(team, _)| team.to_owned()).collect();
+}
+
+/// Simulate the remaining district events of a season.
+///
+/// Teams belong to the districts synced from The Blue Alliance; teams
+/// missing from those lists belong to the district of the first district
+/// event they attend. The top `dcmp_slots` teams of each district advance to the
+/// district championship, after which the top `cmp_slots` teams advance
+/// to the world championship.
+pub fn simulate_season(year: i32, dcmp_slots: usize,
+                       cmp_slots: usize) -> Result<Vec<SeasonResult>> {
+    let mut brier = 0.0f64;
+    let mut team_list = elo(K, CARRY_OVER, None, &mut brier)?;
+    let season = load_events(year)?;
+    let mut members = load_members(year)?;
+    for event in season.iter().filter(|e| !e.championship) {
+        for team in &event.teams {
+            members.entry(team.to_owned()).or_insert(event.district.clone());
+        }
+    }
+    let mut totals: HashMap<String, (i32, usize, usize)> = HashMap::new();
+    for _ in 0..SEASON_RUNS {
+        let mut team_list = team_list.clone();
+        let mut points: HashMap<String, i32> = HashMap::new();
+        let mut counted: HashMap<String, usize> = HashMap::new();
+        for event in season.iter().filter(|e| !e.championship) {
+            for (team, val) in event_points(&mut team_list, year, event, &event.teams) {
+                let count = counted.entry(team.to_owned()).or_insert(0);
+                if *count < district::COUNTED_EVENTS {
+                    *points.entry(team).or_insert(0) += val;
+                }
+                *count += 1;
+            }
+        }
+        // Every team attending a district championship or one of its divisions.
+        let mut championships: Vec<(&str, Vec<String>)> = Vec::new();
+        let mut championship_events: Vec<&SeasonEvent> = season.iter()
+            .filter(|e| e.championship)
+            .collect();
+        // Divisions are played before the finals between their winners.
+        championship_events.sort_by_key(|e| !e.division);
+        for event in championship_events {
+            let index = match championships.iter().position(|c| c.0 == event.district) {
+                Some(i) => i,
+                None => {
+                    championships.push((&event.district, Vec::new()));
+                    championships.len() - 1
+                },
+            };
+            let mut attendees = event.teams.clone();
+            if attendees.len() == 0 && championships[index].1.len() > 0 {
+                // The finals of a divided championship are not modeled.
+                continue;
+            }
+            if attendees.len() == 0 {
+                attendees = standings(&points, &members, &event.district);
+                attendees.retain(|team| !championships[index].1.contains(team));
+                attendees.truncate(dcmp_slots);
+            }
+            for team in &attendees {
+                if !championships[index].1.contains(team) {
+                    totals.entry(team.to_owned()).or_insert((0, 0, 0)).1 += 1;
+                    championships[index].1.push(team.to_owned());
+                }
+            }
+            for (team, val) in event_points(&mut team_list, year, event, &attendees) {
+                *points.entry(team).or_insert(0) += val;
+            }
+        }
+        for (district_key, attendees) in championships {
+            let mut qualified = standings(&points, &members, district_key);
+            qualified.retain(|team| attendees.contains(team));
+            qualified.truncate(cmp_slots);
+            for team in qualified {
+                totals.entry(team).or_insert((0, 0, 0)).2 += 1;
+            }
+        }
+        for (team, val) in points {
+            totals.entry(team).or_insert((0, 0, 0)).0 += val;
+        }
+    }
+    let mut teams = Vec::new();
+    for (team, district_key) in members {
+        let val = totals.get(&team).cloned().unwrap_or((0, 0, 0));
+        teams.push(SeasonResult {
+            elo: team_list.get(&team),
+            key: team,
+            district: district_key,
+            points: val.0 as f64 / SEASON_RUNS as f64,
+            dcmp: val.1 as f64 * 100f64 / SEASON_RUNS as f64,
+            cmp: val.2 as f64 * 100f64 / SEASON_RUNS as f64,
+        });
+    }
+    teams.sort_by(|x, y| match x.district.cmp(&y.district) {
+        Ordering::Equal => y.points.partial_cmp(&x.points).unwrap(),
+        o => o,
+    });
+    return Ok(teams);
+}
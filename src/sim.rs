@@ -0,0 +1,279 @@
+use super::models::{Matche, SCHEDULED};
+use super::elo::Teams;
+use super::tba::TeamEventRanking;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+use rand::{self, Rng};
+
+/// Seeding order used to pair alliances in an elimination bracket.
+const BRACKET_8: &'static [usize] = &[0, 7, 3, 4, 1, 6, 2, 5];
+const BRACKET_4: &'static [usize] = &[0, 3, 1, 2];
+const BRACKET_2: &'static [usize] = &[0, 1];
+
+/// Run the qualification matches of an event once.
+///
+/// Matches which have already been played are counted using their
+/// actual results unless `rankings` already includes them. Returns the
+/// teams in ranked order along with their ranking score.
+pub fn qualification_run(team_list: &mut Teams, match_list: &[Matche],
+                         rankings: &HashMap<String, TeamEventRanking>)
+                         -> Vec<(String, usize)> {
+    let mut rankings = rankings.clone();
+    let mut rng = rand::thread_rng();
+    for m in match_list {
+        if m.is_cancelled() {
+            continue;
+        }
+        if m.is_played() {
+            if rankings.len() > 0 {
+                continue;
+            }
+            // Completed
+            for team in &m.get_red() {
+                let ranking  = rankings.entry(team.to_owned())
+                    .or_insert(TeamEventRanking::new(team));
+                if m.actual_r() > 0.9999 {
+                    ranking.add_win();
+                } else if m.actual_r() < 0.49999 {
+                    ranking.add_loss();
+                } else {
+                    ranking.add_draw();
+                }
+            }
+            for team in &m.get_blue() {
+                let ranking = rankings.entry(team.to_owned())
+                    .or_insert(TeamEventRanking::new(team));
+                if m.actual_b() > 0.999 {
+                    ranking.add_win();
+                } else if m.actual_b() < 0.49999 {
+                    ranking.add_loss();
+                } else {
+                    ranking.add_draw();
+                }
+            }
+        } else {
+            // simulate this.
+            let result = team_list.simulate(m);
+            let extra_prob = rng.gen::<f64>();
+            let mut red_extra_prob = 1f64;
+            let mut blue_extra_prob = 1f64;
+            for team in &m.get_red() {
+                let ranking = rankings.entry(team.to_owned())
+                    .or_insert(TeamEventRanking::new(team));
+                if result {
+                    ranking.add_win();
+                } else {
+                    ranking.add_loss();
+                }
+                if ranking.matches_played > 3 {
+                    red_extra_prob *= 1f64 - ranking.extra_prob()
+                        * (ranking.matches_played as f64 / 4f64);
+                }
+            }
+            for team in &m.get_blue() {
+                let ranking = rankings.entry(team.to_owned())
+                    .or_insert(TeamEventRanking::new(team));
+                if !result {
+                    ranking.add_win();
+                } else {
+                    ranking.add_loss();
+                }
+                if ranking.matches_played > 3 {
+                    blue_extra_prob *= 1f64 - ranking.extra_prob();
+                } else {
+                    blue_extra_prob *= 1f64 - ranking.extra_prob()
+                        * (ranking.matches_played as f64 / 4f64);
+                }
+            }
+            if extra_prob > red_extra_prob {
+                for team in &m.get_red() {
+                    let ranking = rankings.entry(team.to_owned())
+                        .or_insert(TeamEventRanking::new(team));
+                    ranking.add_extra();
+                }
+            }
+            let extra_prob = rng.gen::<f64>();
+            if extra_prob > blue_extra_prob {
+                for team in &m.get_blue() {
+                    let ranking = rankings.entry(team.to_owned())
+                        .or_insert(TeamEventRanking::new(team));
+                    ranking.add_extra();
+                }
+            }
+        }
+    }
+    let mut teams = Vec::new();
+    for (team, val) in rankings.iter_mut() {
+        teams.push((team.to_owned(), val.to_usize(),
+                    val.sort_orders.get(1).unwrap_or(&0.0f64).to_owned()));
+    }
+    teams.sort_by(|x, y| match y.1.partial_cmp(&x.1) {
+        Some(Ordering::Less) => Ordering::Less,
+        Some(Ordering::Greater) => Ordering::Greater,
+        _ => y.2.partial_cmp(&x.2).unwrap(),
+    });
+    return teams.into_iter().map(|(team, val, _)| (team, val)).collect();
+}
+
+/// Build a random qualification schedule in which every team
+/// plays at least `rounds` matches.
+///
+/// When the number of slots is not divisible by six, the final
+/// match is filled with surrogate appearances from random teams.
+pub fn random_schedule(event_key: &str, team_keys: &[String], rounds: usize) -> Vec<Matche> {
+    return random_matches(event_key, team_keys, (rounds * team_keys.len() + 5) / 6);
+}
+
+/// Build a random qualification schedule of `count` matches.
+///
+/// Every team plays the same number of matches, apart from the
+/// surrogate appearances filling the last round, which each go
+/// to a different team.
+pub fn random_matches(event_key: &str, team_keys: &[String], count: usize) -> Vec<Matche> {
+    let mut rng = rand::thread_rng();
+    let mut slots = Vec::new();
+    if team_keys.len() < 6 {
+        return Vec::new();
+    }
+    while slots.len() < count * 6 {
+        let mut round = team_keys.to_vec();
+        rng.shuffle(&mut round);
+        slots.append(&mut round);
+    }
+    slots.truncate(count * 6);
+    let mut schedule = Vec::new();
+    for (i, chunk) in slots.chunks(6).enumerate() {
+        schedule.push(Matche {
+            id: format!("{}_qm{}", event_key, i + 1),
+            comp_level: String::from("qm"),
+            match_number: i as i32 + 1,
+            set_number: 1,
+            event_id: event_key.to_owned(),
+            red_score: 0,
+            blue_score: 0,
+            red_teams: chunk[..3].join(" "),
+            blue_teams: chunk[3..].join(" "),
+            status: String::from(SCHEDULED),
+            time: None,
+            actual_time: None,
+        });
+    }
+    return schedule;
+}
+
+/// Pick alliances from a ranked list of teams.
+///
+/// The highest ranked team still available captains each alliance
+/// and picks the highest rated team still available. The second
+/// round of picks runs in reverse order.
+pub fn select_alliances(team_list: &mut Teams, ranked: &[String]) -> Vec<Vec<String>> {
+    let count = match ranked.len() / 3 {
+        0 | 1 => 0,
+        2 | 3 => 2,
+        4..=7 => 4,
+        _ => 8,
+    };
+    let mut available: Vec<String> = ranked.to_vec();
+    let mut alliances: Vec<Vec<String>> = Vec::new();
+    for _ in 0..count {
+        let captain = available.remove(0);
+        let pick = best_available(team_list, &mut available);
+        alliances.push(vec![captain, pick]);
+    }
+    for i in (0..count).rev() {
+        let pick = best_available(team_list, &mut available);
+        alliances[i].push(pick);
+    }
+    return alliances;
+}
+
+fn best_available(team_list: &mut Teams, available: &mut Vec<String>) -> String {
+    let mut best = 0;
+    for i in 1..available.len() {
+        if team_list.get(&available[i]) > team_list.get(&available[best]) {
+            best = i;
+        }
+    }
+    return available.remove(best);
+}
+
+/// Run an elimination bracket once.
+///
+/// Every round is a best-of-three series. Returns the number of
+/// matches won by each alliance along with the index of the winner.
+pub fn playoff_run(team_list: &mut Teams, event_key: &str,
+                   alliances: &[Vec<String>]) -> (Vec<usize>, usize) {
+    let mut wins = vec![0; alliances.len()];
+    let mut remaining: Vec<usize> = match alliances.len() {
+        8 => BRACKET_8.to_vec(),
+        4 => BRACKET_4.to_vec(),
+        2 => BRACKET_2.to_vec(),
+        _ => return (wins, 0),
+    };
+    let mut levels = vec!["f", "sf", "qf"];
+    levels.truncate(remaining.len().trailing_zeros() as usize);
+    while remaining.len() > 1 {
+        let level = levels.pop().unwrap_or("f");
+        let mut advancing = Vec::new();
+        for (set, pair) in remaining.chunks(2).enumerate() {
+            let (red, blue) = (pair[0], pair[1]);
+            let mut red_wins = 0;
+            let mut blue_wins = 0;
+            let mut number = 1;
+            while red_wins < 2 && blue_wins < 2 {
+                let m = playoff_match(event_key, level, set as i32 + 1, number,
+                                      &alliances[red], &alliances[blue]);
+                if team_list.simulate(&m) {
+                    red_wins += 1;
+                } else {
+                    blue_wins += 1;
+                }
+                number += 1;
+            }
+            wins[red] += red_wins;
+            wins[blue] += blue_wins;
+            advancing.push(if red_wins > blue_wins { red } else { blue });
+        }
+        remaining = advancing;
+    }
+    return (wins, remaining[0]);
+}
+
+fn playoff_match(event_key: &str, level: &str, set: i32, number: i32,
+                 red: &[String], blue: &[String]) -> Matche {
+    Matche {
+        id: format!("{}_{}{}m{}", event_key, level, set, number),
+        comp_level: level.to_owned(),
+        match_number: number,
+        set_number: set,
+        event_id: event_key.to_owned(),
+        red_score: 0,
+        blue_score: 0,
+        red_teams: red.join(" "),
+        blue_teams: blue.join(" "),
+        status: String::from(SCHEDULED),
+        time: None,
+        actual_time: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_matches_fill_the_last_round_with_surrogates() {
+        let team_keys: Vec<String> = (1..39).map(|t| format!("frc{}", t)).collect();
+        let schedule = random_matches("2017tst", &team_keys, 77);
+        assert_eq!(schedule.len(), 77);
+        let mut played: HashMap<String, usize> = HashMap::new();
+        for m in &schedule {
+            for team in m.get_red().into_iter().chain(m.get_blue()) {
+                *played.entry(team).or_insert(0) += 1;
+            }
+        }
+        assert_eq!(played.len(), 38);
+        assert_eq!(played.values().filter(|&&n| n == 12).count(), 32);
+        assert_eq!(played.values().filter(|&&n| n == 13).count(), 6);
+    }
+}
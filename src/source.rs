@@ -0,0 +1,178 @@
+use client::{Client, Mode, Stamp};
+use error::Result;
+use models::{EventJSON, GameMatch, DistrictListJSON, AllianceJSON, AwardJSON, TeamJSON,
+             NewSyncState};
+use tba::{self, RankingResultJSON};
+use serde::Deserialize;
+use serde_json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::PathBuf;
+
+/// Somewhere events, matches and rankings can be read from.
+///
+/// Methods returning an `Option` return `None` when the data
+/// has not changed since it was last read, or does not exist.
+pub trait DataSource: Send + Sync {
+    fn events(&self, year: i32) -> Result<Option<Vec<EventJSON>>>;
+    fn event_matches(&self, key: &str) -> Result<Option<Vec<GameMatch>>>;
+    fn rankings(&self, key: &str) -> Result<Option<RankingResultJSON>>;
+    fn event_teams(&self, key: &str) -> Result<Option<Vec<String>>>;
+    fn districts(&self, year: i32) -> Result<Option<Vec<DistrictListJSON>>>;
+    fn district_teams(&self, key: &str) -> Result<Option<Vec<String>>>;
+    fn alliances(&self, key: &str) -> Result<Option<Vec<AllianceJSON>>>;
+    fn awards(&self, key: &str) -> Result<Option<Vec<AwardJSON>>>;
+    /// A page of teams. Pages past the last team are empty.
+    fn teams(&self, page: usize) -> Result<Option<Vec<TeamJSON>>>;
+
+    /// Forget the stamps of URLs starting with `prefix` so they are read in full.
+    fn forget(&self, _prefix: &str) {}
+
+    /// Stamps of the responses read so far whose URL starts with
+    /// `prefix`, to be saved along with the data they describe.
+    fn stamps(&self, _prefix: &str) -> Vec<NewSyncState> {
+        return Vec::new();
+    }
+}
+
+/// Reads from The Blue Alliance.
+pub struct TbaSource {
+    client: Client,
+}
+
+impl TbaSource {
+    /// `history` holds the stamps of earlier responses.
+    pub fn new(history: HashMap<String, Stamp>, mode: Mode) -> Result<TbaSource> {
+        return Ok(TbaSource {
+            client: Client::new(history, mode)?,
+        });
+    }
+}
+
+impl DataSource for TbaSource {
+    fn events(&self, year: i32) -> Result<Option<Vec<EventJSON>>> {
+        return tba::get_events(&self.client, year);
+    }
+
+    fn event_matches(&self, key: &str) -> Result<Option<Vec<GameMatch>>> {
+        return tba::get_event_matches(&self.client, key);
+    }
+
+    fn rankings(&self, key: &str) -> Result<Option<RankingResultJSON>> {
+        return tba::get_event_rankings(&self.client, key);
+    }
+
+    fn event_teams(&self, key: &str) -> Result<Option<Vec<String>>> {
+        return tba::get_event_teams(&self.client, key);
+    }
+
+    fn districts(&self, year: i32) -> Result<Option<Vec<DistrictListJSON>>> {
+        return tba::get_districts(&self.client, year);
+    }
+
+    fn district_teams(&self, key: &str) -> Result<Option<Vec<String>>> {
+        return tba::get_district_teams(&self.client, key);
+    }
+
+    fn alliances(&self, key: &str) -> Result<Option<Vec<AllianceJSON>>> {
+        return tba::get_event_alliances(&self.client, key);
+    }
+
+    fn awards(&self, key: &str) -> Result<Option<Vec<AwardJSON>>> {
+        return tba::get_event_awards(&self.client, key);
+    }
+
+    fn teams(&self, page: usize) -> Result<Option<Vec<TeamJSON>>> {
+        return tba::get_teams(&self.client, page);
+    }
+
+    fn forget(&self, prefix: &str) {
+        let mut history = self.client.history.lock().expect("Could not lock history");
+        history.retain(|url, _| !url.starts_with(prefix));
+    }
+
+    fn stamps(&self, prefix: &str) -> Vec<NewSyncState> {
+        let history = self.client.history.lock().expect("Could not lock history");
+        return history.iter()
+            .filter(|&(url, _)| url.starts_with(prefix))
+            .map(|(url, stamp)| NewSyncState {
+                url: url.clone(),
+                last_modified: stamp.last_modified.clone(),
+                etag: stamp.etag.clone(),
+            }).collect();
+    }
+}
+
+/// Reads recorded responses from a directory.
+///
+/// Each response is stored as JSON at the path of its API URL, so
+/// the matches of `2017casj` are read from
+/// `event/2017casj/matches/simple.json`.
+pub struct FixtureSource {
+    dir: PathBuf,
+}
+
+impl FixtureSource {
+    pub fn new(dir: &str) -> FixtureSource {
+        FixtureSource {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Read the fixture for `url`, or `None` if there is none.
+    fn load<T: Deserialize>(&self, url: &str) -> Result<Option<T>> {
+        let path = self.dir.join(format!("{}.json", url));
+        let mut file = match File::open(&path) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut data = String::new();
+        file.read_to_string(&mut data)?;
+        return Ok(Some(serde_json::from_str(&data)?));
+    }
+}
+
+impl DataSource for FixtureSource {
+    fn events(&self, year: i32) -> Result<Option<Vec<EventJSON>>> {
+        return self.load(&format!("events/{}", year));
+    }
+
+    fn event_matches(&self, key: &str) -> Result<Option<Vec<GameMatch>>> {
+        return self.load(&format!("event/{}/matches/simple", key));
+    }
+
+    fn rankings(&self, key: &str) -> Result<Option<RankingResultJSON>> {
+        let rankings: Option<Option<RankingResultJSON>> =
+            self.load(&format!("event/{}/rankings", key))?;
+        return Ok(rankings.and_then(|r| r));
+    }
+
+    fn event_teams(&self, key: &str) -> Result<Option<Vec<String>>> {
+        return self.load(&format!("event/{}/teams/keys", key));
+    }
+
+    fn districts(&self, year: i32) -> Result<Option<Vec<DistrictListJSON>>> {
+        return self.load(&format!("districts/{}", year));
+    }
+
+    fn district_teams(&self, key: &str) -> Result<Option<Vec<String>>> {
+        return self.load(&format!("district/{}/teams/keys", key));
+    }
+
+    fn alliances(&self, key: &str) -> Result<Option<Vec<AllianceJSON>>> {
+        let alliances: Option<Option<Vec<AllianceJSON>>> =
+            self.load(&format!("event/{}/alliances", key))?;
+        return Ok(alliances.and_then(|a| a));
+    }
+
+    fn awards(&self, key: &str) -> Result<Option<Vec<AwardJSON>>> {
+        return self.load(&format!("event/{}/awards", key));
+    }
+
+    fn teams(&self, page: usize) -> Result<Option<Vec<TeamJSON>>> {
+        let teams = self.load(&format!("teams/{}", page))?;
+        return Ok(Some(teams.unwrap_or(Vec::new())));
+    }
+}
@@ -0,0 +1,88 @@
+use super::elo::Standing;
+use super::{get_matches, replay_matches};
+use error::Result;
+use std::collections::HashMap;
+use std::cmp::Ordering;
+
+/// How strong the teams attending an event were as it began.
+#[derive(Serialize, Clone)]
+pub struct EventStrength {
+    pub key: String,
+    pub name: String,
+    pub year: i32,
+    pub event_type: i32,
+    pub teams: usize,
+    /// The mean rating of every team which played.
+    pub mean: f64,
+    /// The mean rating of the best `top_n` teams.
+    pub top: f64,
+    /// Both of the above in standard deviations from the mean rating
+    /// of every team which played that season.
+    pub mean_z: f64,
+    pub top_z: f64,
+}
+
+/// Replay every match, noting the ratings of each event's teams as it
+/// began, and rank the events by how far their mean rating stood above
+/// the rest of their season. The strongest event comes first.
+pub fn event_strengths(top_n: usize, offseason: Option<f64>) -> Result<Vec<EventStrength>> {
+    let (event_list, match_list) = get_matches(offseason.is_some())?;
+    let mut attendees: HashMap<&str, Vec<String>> = HashMap::new();
+    for m in &match_list {
+        let teams = attendees.entry(m.event_id.as_str()).or_insert(Vec::new());
+        for team in m.get_red().into_iter().chain(m.get_blue()) {
+            if !teams.contains(&team) {
+                teams.push(team);
+            }
+        }
+    }
+    let mut ratings: HashMap<&str, Vec<f64>> = HashMap::new();
+    // The rating of every team as it began each season.
+    let mut seasons: HashMap<usize, HashMap<String, f64>> = HashMap::new();
+    replay_matches(&event_list, &match_list, offseason, |team_list, season, m| {
+        if ratings.contains_key(m.event_id.as_str()) {
+            return;
+        }
+        team_list.advance_to(season);
+        let season_ratings = seasons.entry(season).or_insert(HashMap::new());
+        let mut event_ratings = Vec::new();
+        for team in &attendees[m.event_id.as_str()] {
+            let rating = team_list.get(team);
+            season_ratings.entry(team.clone()).or_insert(rating);
+            event_ratings.push(rating);
+        }
+        ratings.insert(m.event_id.as_str(), event_ratings);
+    })?;
+    let standings: HashMap<usize, Standing> = seasons.into_iter()
+        .map(|(season, teams)| (season, Standing::new(teams.values().cloned().collect())))
+        .collect();
+    let mut strengths = Vec::new();
+    for e in &event_list {
+        let mut event_ratings = match ratings.remove(e.id.as_str()) {
+            Some(r) => r,
+            None => continue,
+        };
+        event_ratings.sort_by(|x, y| y.partial_cmp(x).unwrap_or(Ordering::Equal));
+        let mean = average(&event_ratings);
+        let top = average(&event_ratings[..top_n.min(event_ratings.len())]);
+        let standing = &standings[&(e.year as usize)];
+        strengths.push(EventStrength {
+            key: e.id.clone(),
+            name: e.name.clone(),
+            year: e.year,
+            event_type: e.event_type,
+            teams: event_ratings.len(),
+            mean: mean,
+            top: top,
+            mean_z: standing.z(mean),
+            top_z: standing.z(top),
+        });
+    }
+    strengths.sort_by(|x, y| y.mean_z.partial_cmp(&x.mean_z).unwrap_or(Ordering::Equal));
+    return Ok(strengths);
+}
+
+/// The mean of `values`, or 0 if there are none.
+fn average(values: &[f64]) -> f64 {
+    return values.iter().sum::<f64>() / values.len().max(1) as f64;
+}
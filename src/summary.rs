@@ -0,0 +1,93 @@
+use super::models::Matche;
+use super::{db_connect, event_teams, get_matches, replay_matches};
+use error::Result;
+use std::cmp::Ordering;
+
+/// The number of upsets listed.
+const UPSETS: usize = 5;
+
+/// How strong an event was and how it played out.
+pub struct EventSummary {
+    pub name: String,
+    /// The mean rating of the teams attending, as it was when the event began.
+    pub average: f64,
+    /// The ratings of the 8th and 24th best teams, if there were that many.
+    pub eighth: Option<f64>,
+    pub twenty_fourth: Option<f64>,
+    /// Matches whose winner was predicted, out of every match not tied.
+    pub correct: usize,
+    pub decided: usize,
+    /// The matches least expected to be won by their winners, along
+    /// with how unlikely that was, one less the chance the winner was given.
+    pub upsets: Vec<(Matche, f64)>,
+    /// How much each team's rating changed over the event, largest gain first.
+    pub changes: Vec<(String, f64)>,
+}
+
+/// Replay every match up to the end of an event, noting the ratings
+/// of its teams beforehand and each prediction made during it.
+/// Returns `None` if none of its matches have been played.
+pub fn summarize(event_key: &str, offseason: Option<f64>) -> Result<Option<EventSummary>> {
+    let (event_list, match_list) = get_matches(offseason.is_some())?;
+    let last = match match_list.iter().rposition(|m| m.event_id == event_key) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    let conn = db_connect()?;
+    let mut teams = event_teams(&conn, event_key)?;
+    for m in match_list.iter().filter(|m| m.event_id == event_key) {
+        teams.extend(m.get_red().into_iter().chain(m.get_blue()));
+    }
+    teams.sort();
+    teams.dedup();
+    let mut before = None;
+    let mut correct = 0;
+    let mut decided = 0;
+    let mut upsets = Vec::new();
+    let mut team_list = replay_matches(&event_list, &match_list[..last + 1], offseason,
+                                       |team_list, season, m| {
+        if m.event_id != event_key {
+            return;
+        }
+        team_list.advance_to(season);
+        if before.is_none() {
+            let ratings: Vec<f64> = teams.iter().map(|t| team_list.get(t)).collect();
+            before = Some(ratings);
+        }
+        let p = team_list.predict(m);
+        let winner = match m.actual_r() {
+            r if r > 0.9999 => Some(p),
+            r if r < 0.0001 => Some(1f64 - p),
+            _ => None,
+        };
+        if let Some(chance) = winner {
+            decided += 1;
+            if chance > 0.5f64 {
+                correct += 1;
+            }
+            upsets.push((m.clone(), 1f64 - chance));
+        }
+    })?;
+    let before = before.unwrap_or(Vec::new());
+    let mut ranked = before.clone();
+    ranked.sort_by(|x, y| y.partial_cmp(x).unwrap_or(Ordering::Equal));
+    upsets.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(Ordering::Equal));
+    upsets.truncate(UPSETS);
+    let mut changes: Vec<(String, f64)> = teams.iter().zip(before.iter())
+        .map(|(t, r)| (t.clone(), team_list.get(t) - r))
+        .collect();
+    changes.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(Ordering::Equal));
+    let name = event_list.iter().find(|e| e.id == event_key)
+        .map(|e| e.name.clone())
+        .unwrap_or(String::new());
+    return Ok(Some(EventSummary {
+        name: name,
+        average: before.iter().sum::<f64>() / before.len() as f64,
+        eighth: ranked.get(7).cloned(),
+        twenty_fourth: ranked.get(23).cloned(),
+        correct: correct,
+        decided: decided,
+        upsets: upsets,
+        changes: changes,
+    }));
+}
@@ -1,121 +1,77 @@
-use curl::easy::{Easy, List};
 use std::str;
-use std::env;
 use models;
-use std::sync::Arc;
-use std::sync::Mutex;
-use std::collections::HashMap;
+use client::{Client, Stamp};
+use serde::Deserialize;
 use serde_json;
-use std::error::Error;
+use error::{Error, Result};
 use CURRENT_YEAR;
 
-pub struct Response {
-    pub code: u32,
-    pub data: Vec<u8>,
-    pub last_modified: String,
+pub fn get_events(client: &Client, year: i32) -> Result<Option<Vec<models::EventJSON>>> {
+    // The current season is always fetched in full.
+    return get_modified(client, &format!("events/{}", year), year != CURRENT_YEAR);
 }
 
-pub fn get_events(history: Arc<Mutex<HashMap<String, String>>>,
-                  year: i32) -> Option<Vec<models::EventJSON>> {
-    let url = format!("events/{}", year);
-    let mut last_time = String::new();
-    if year != CURRENT_YEAR {
-        let history = history.lock()
-            .expect("Could not lock history for getting event time");
-        match history.get(&url) {
-            Some(date) => last_time.push_str(&date),
-            None => {},
-        };
-    }
-    let response = request(&url, &last_time);
-    if response.code != 200 { //&& year < CURRENT_YEAR {
-        return None;
-    }
-    {
-        let mut history = history.lock()
-            .expect("Could not lock history for setting event time");
-        history.insert(url, response.last_modified.trim().to_string());
-    }
-    if response.data.len() > 0 {
-        let data_str = str::from_utf8(&response.data)
-            .expect("Could not load data string");
-        let event_list: Vec<models::EventJSON> = serde_json::from_str(&data_str)
-            .expect("Could not parse events JSON");
-        return Some(event_list);
+pub fn get_event_matches(client: &Client, key: &str) -> Result<Option<Vec<models::GameMatch>>> {
+    let url = format!("event/{}/matches/simple", key);
+    let game_matches = get_modified(client, &url, true)?;
+    if game_matches.is_some() {
+        println!("Updating {}", url);
     }
-    return None;
+    return Ok(game_matches);
 }
 
-pub fn get_event_matches(history: Arc<Mutex<HashMap<String, String>>>,
-                         key: &str) -> Option<Vec<models::GameMatch>> {
-    let url = format!("event/{}/matches/simple", key);
-    let mut last_time = String::new();
-    let response;
+/// Fetch and parse a resource. When `cached` is set, the resource is
+/// only returned if it has changed since it was last recorded in
+/// `history`; otherwise `None` is returned.
+fn get_modified<T: Deserialize>(client: &Client, url: &str, cached: bool) -> Result<Option<T>> {
+    let mut stamp = None;
+    if cached {
+        let history = client.history.lock()
+            .expect("Could not get history for reading");
+        stamp = history.get(url).cloned();
+    }
+    let response = client.request(url, stamp.as_ref())?;
+    match response.code {
+        200 => {},
+        304 => return Ok(None),
+        code => return Err(Error::Status(url.to_owned(), code)),
+    }
+    let data_str = str::from_utf8(&response.data)?;
+    let parsed = serde_json::from_str(&data_str)?;
     {
-        let history = history.lock()
-            .expect("Could not get history for match reading");
-        match history.get(&url) {
-            Some(date) => last_time.push_str(&date),
-            None => {},
-        };
-    }
-    response = request(&url, &last_time);
-    if response.code != 200 {
-        return None;
-    }
-    println!("Updating {}", url);
-    {
-        let mut history = history.lock()
-            .expect("Could not get history for match writing");
-        history.insert(url, response.last_modified.trim().to_string());
-    }
-    let data_str = str::from_utf8(&response.data)
-        .expect("Could not load match data string");
-    match serde_json::from_str(&data_str) {
-        Ok(m) => return Some(m),
-        Err(e) => {
-            println!("Error: {}", e.description());
-            return None;
-        },
-    }
+        let mut history = client.history.lock()
+            .expect("Could not get history for writing");
+        history.insert(url.to_owned(), Stamp {
+            last_modified: response.last_modified.trim().to_string(),
+            etag: response.etag,
+        });
+    }
+    return Ok(Some(parsed));
 }
 
-pub fn request(url_ext: &str, date: &str) -> Response {
-    let request_url = format!("https://www.thebluealliance.com/api/v3/{}", url_ext);
-    let mut easy = Easy::new();
-    let mut list = List::new();
-    let mut data = Vec::new();
-    list.append("X-TBA-App-Id: Carl Colglazier:FRC ELO:0.0.0").unwrap();
-    list.append(&format!("X-TBA-Auth-Key: {}", env::var("TBA_KEY")
-                         .expect("Auth Key"))).expect("Add auth key");
-    if date.len() > 0 {
-        let time_header = format!("If-Modified-Since: {}", date);
-        list.append(&time_header).unwrap();
-    }
-    easy.http_headers(list).unwrap();
-    easy.url(&request_url).unwrap();
-    let mut headers = String::new();
-    {
-        let mut transfer = easy.transfer();
-        transfer.write_function(|new_data| {
-            data.extend_from_slice(new_data);
-            Ok(new_data.len())
-        }).unwrap();
-        transfer.header_function(|header| {
-            let s = str::from_utf8(header).unwrap().to_string();
-            if s.starts_with("Last-Modified: ") {
-                headers.push_str(&s[15..]);
-            }
-            true
-        }).unwrap();
-        transfer.perform().unwrap();
-    }
-    let code = easy.response_code().unwrap();
-    return Response {
-        code: code,
-        data: data,
-        last_modified: headers,
-    };
+pub fn get_districts(client: &Client, year: i32) -> Result<Option<Vec<models::DistrictListJSON>>> {
+    return get_modified(client, &format!("districts/{}", year), true);
+}
+
+pub fn get_district_teams(client: &Client, key: &str) -> Result<Option<Vec<String>>> {
+    return get_modified(client, &format!("district/{}/teams/keys", key), true);
+}
+
+pub fn get_event_rankings(client: &Client, key: &str) -> Result<Option<RankingResultJSON>> {
+    // Events without rankings return `null`.
+    let rankings: Option<Option<RankingResultJSON>> =
+        get_modified(client, &format!("event/{}/rankings", key), true)?;
+    return Ok(rankings.and_then(|r| r));
+}
+
+pub fn get_event_alliances(client: &Client, key: &str) -> Result<Option<Vec<models::AllianceJSON>>> {
+    let alliances: Option<Option<Vec<models::AllianceJSON>>> =
+        get_modified(client, &format!("event/{}/alliances", key), true)?;
+    return Ok(alliances.and_then(|a| a));
+}
+
+pub fn get_event_awards(client: &Client, key: &str) -> Result<Option<Vec<models::AwardJSON>>> {
+    return get_modified(client, &format!("event/{}/awards", key), true);
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -141,6 +97,7 @@ impl WinLossRecord {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct TeamEventRanking {
+    pub rank: usize,
     pub matches_played: usize,
     extra_stats: Vec<usize>,
     pub sort_orders: Vec<f64>,
@@ -156,6 +113,7 @@ pub struct RankingResultJSON {
 impl TeamEventRanking {
     pub fn new(key: &str) -> TeamEventRanking {
         let mut new = TeamEventRanking {
+            rank: 0,
             matches_played: 0,
             extra_stats: Vec::new(),
             sort_orders: Vec::new(),
@@ -205,41 +163,44 @@ impl TeamEventRanking {
         }
     }
 
-    pub fn key(&self) -> String {
-        return self.team_key.clone();
+    pub fn prepare(&self, event: &str) -> models::NewRanking {
+        models::NewRanking {
+            id: format!("{}_{}", event, self.team_key),
+            event_id: event.to_owned(),
+            team_key: self.team_key.clone(),
+            rank: self.rank as i32,
+            wins: self.record.wins as i32,
+            losses: self.record.losses as i32,
+            ties: self.record.ties as i32,
+            matches_played: self.matches_played as i32,
+            ranking_score: self.extra_stats.first().cloned()
+                .unwrap_or(self.record.to_usize()) as i32,
+            tiebreaker: self.sort_orders.get(1).cloned().unwrap_or(0f64),
+        }
     }
-}
 
-pub fn get_rankings(key: &str) -> Option<RankingResultJSON> {
-    let url = format!("event/{}/rankings", key);
-    let response = request(&url, &String::new());
-    if response.code != 200 {
-        return None;
+    /// Rebuild a ranking stored during sync.
+    pub fn from_ranking(ranking: &models::Ranking) -> TeamEventRanking {
+        TeamEventRanking {
+            rank: ranking.rank as usize,
+            matches_played: ranking.matches_played as usize,
+            extra_stats: vec![ranking.ranking_score as usize],
+            sort_orders: vec![0f64, ranking.tiebreaker],
+            record: WinLossRecord {
+                losses: ranking.losses as usize,
+                ties: ranking.ties as usize,
+                wins: ranking.wins as usize,
+            },
+            team_key: ranking.team_key.clone(),
+        }
     }
-    let data_str = str::from_utf8(&response.data)
-        .expect("Could not load event rankings string");
-    let _ : RankingResultJSON = match serde_json::from_str(&data_str) {
-        Ok(m) => return Some(m),
-        Err(e) => {
-            println!("Error: {}", e.description());
-            return None;
-        },
-    };
 }
 
-pub fn get_event_teams(key: &str) -> Option<Vec<String>> {
-    let url = format!("event/{}/teams/keys", key);
-    let response = request(&url, &String::new());
-    if response.code != 200 {
-        return None;
-    }
-    let data_str = str::from_utf8(&response.data)
-        .expect("Could not load event teams string");
-    let _ : Vec<String> = match serde_json::from_str(&data_str) {
-        Ok(m) => return Some(m),
-        Err(e) => {
-            println!("Error: {}", e.description());
-            return None;
-        },
-    };
+pub fn get_event_teams(client: &Client, key: &str) -> Result<Option<Vec<String>>> {
+    return get_modified(client, &format!("event/{}/teams/keys", key), true);
+}
+
+/// A page of up to 500 teams. Pages past the last team are empty.
+pub fn get_teams(client: &Client, page: usize) -> Result<Option<Vec<models::TeamJSON>>> {
+    return get_modified(client, &format!("teams/{}", page), true);
 }
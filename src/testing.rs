@@ -0,0 +1,79 @@
+use models::{Matche, PLAYED};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+/// Tests which change the environment, such as `DATABASE_URL` or
+/// `TBA_URL`, take turns.
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// Hold the environment until the returned guard is dropped.
+pub fn lock_environment() -> MutexGuard<'static, ()> {
+    return ENVIRONMENT.lock().unwrap_or_else(|e| e.into_inner());
+}
+
+/// A played qualification match of `event`.
+pub fn played(event: &str, number: i32, red: &[&str], blue: &[&str],
+              red_score: i32, blue_score: i32) -> Matche {
+    Matche {
+        id: format!("{}_qm{}", event, number),
+        comp_level: String::from("qm"),
+        match_number: number,
+        set_number: 1,
+        event_id: event.to_owned(),
+        red_score: red_score,
+        blue_score: blue_score,
+        red_teams: red.join(" "),
+        blue_teams: blue.join(" "),
+        status: String::from(PLAYED),
+        time: None,
+        actual_time: None,
+    }
+}
+
+/// A stand-in for The Blue Alliance which answers one request with each
+/// of `responses` in turn, closing the connection after each.
+///
+/// Returns the URL to serve from along with a handle which yields the
+/// head of every request received once all responses have been sent.
+pub fn stand_in(responses: Vec<String>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/v3", listener.local_addr().unwrap());
+    let handle = thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            {
+                let mut reader = BufReader::new(&stream);
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            requests.push(request);
+        }
+        return requests;
+    });
+    return (url, handle);
+}
+
+/// A response with `body` and the given extra headers.
+pub fn ok(headers: &[&str], body: &str) -> String {
+    let mut head = String::from("HTTP/1.1 200 OK\r\nConnection: close\r\n");
+    for header in headers {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    return format!("{}Content-Length: {}\r\n\r\n{}", head, body.len(), body);
+}
+
+/// A response saying nothing has changed.
+pub fn not_modified() -> String {
+    return String::from("HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n");
+}
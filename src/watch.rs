@@ -0,0 +1,87 @@
+use super::models::{Event, Matche, prepare_matches};
+use super::elo::Teams;
+use super::{db_connect, elo, event_rankings, event_weight, forecast, play_order,
+            print_forecast, print_probabilities, team_names, K, CARRY_OVER};
+use source::DataSource;
+use error::Result;
+use diesel;
+use diesel::prelude::*;
+use std::collections::HashSet;
+use std::thread;
+use std::time::Duration;
+
+/// Follow an event as it is played.
+///
+/// The event's matches and rankings are requested from `source` every
+/// `interval` seconds, but are only returned once they have changed, and
+/// are stored as they arrive. The forecast starts from the stored
+/// rankings just as `sim` does. Each newly
+/// completed match updates the ratings in place before the remaining win
+/// probabilities and the ranking forecast are printed again. Stops once
+/// the finals have been played and no matches remain, returning the
+/// ratings as they then stand. Matches of an off-season event only
+/// change the ratings if `offseason` is given.
+pub fn watch(source: &dyn DataSource, event_key: &str, interval: u64,
+             offseason: Option<f64>) -> Result<Teams> {
+    use schema::matches::dsl::*;
+    let mut brier = 0.0f64;
+    let mut team_list = elo(K, CARRY_OVER, offseason, &mut brier)?;
+    let names = team_names()?;
+    let conn = db_connect()?;
+    // Rate the event's matches just as a full replay would, in the
+    // event's season and with its weight.
+    let weight = match ::schema::events::table.find(event_key).first::<Event>(&conn) {
+        Ok(e) => {
+            team_list.advance_to(e.year as usize);
+            event_weight(&e, offseason.unwrap_or(0f64), team_list.weights())
+        },
+        Err(diesel::result::Error::NotFound) => 1f64,
+        Err(e) => return Err(e.into()),
+    };
+    let mut match_list = matches
+        .filter(event_id.eq(event_key))
+        .order(match_number)
+        .load::<Matche>(&conn)?;
+    // Matches already stored as played are part of the ratings.
+    let mut processed: HashSet<String> = match_list.iter()
+        .filter(|m| m.is_played())
+        .map(|m| m.id.clone()).collect();
+    loop {
+        if let Some(game_matches) = source.event_matches(event_key)? {
+            let new_matches = prepare_matches(&game_matches);
+            diesel::insert_or_replace(&new_matches).into(matches).execute(&conn)?;
+            if let Some(r) = source.rankings(event_key)? {
+                let new_rankings: Vec<_> = r.rankings.iter()
+                    .map(|x| x.prepare(event_key)).collect();
+                diesel::insert_or_replace(&new_rankings)
+                    .into(::schema::rankings::table).execute(&conn)?;
+            }
+            let unordered = new_matches.iter().map(|x| x.to_match()).collect();
+            match_list = play_order(unordered, 0).into_iter().map(|(_, m)| m).collect();
+            for m in &match_list {
+                if !m.is_played() || processed.contains(&m.id) {
+                    continue;
+                }
+                team_list.process_weighted(m, weight);
+                processed.insert(m.id.clone());
+                println!("Completed {} ({} - {})", m.id, m.red_score, m.blue_score);
+            }
+            let remaining: Vec<Matche> = match_list.iter()
+                .filter(|m| m.is_scheduled())
+                .cloned().collect();
+            print_probabilities(&mut team_list, &remaining);
+            let qualifications: Vec<Matche> = match_list.iter()
+                .filter(|m| m.comp_level == "qm")
+                .cloned().collect();
+            if remaining.iter().any(|m| m.comp_level == "qm") {
+                // The forecast starts from the rankings as they stand.
+                let rankings = event_rankings(&conn, event_key)?;
+                print_forecast(&forecast(&mut team_list, &qualifications, &rankings), &names);
+            }
+            if remaining.len() == 0 && match_list.iter().any(|m| m.comp_level == "f") {
+                return Ok(team_list);
+            }
+        }
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
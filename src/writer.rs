@@ -0,0 +1,197 @@
+use super::RequestData;
+use models::*;
+use error::{Error, Result};
+use diesel;
+use diesel::prelude::*;
+use diesel::connection::SimpleConnection;
+use diesel::sqlite::SqliteConnection;
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Data read during sync, waiting to be written.
+pub enum Batch {
+    /// The districts of a season and the teams in each.
+    Districts {
+        year: i32,
+        districts: Vec<DistrictListJSON>,
+        members: Vec<(String, Vec<String>)>,
+        stamps: Vec<NewSyncState>,
+    },
+    /// Everything read for a single event.
+    Event {
+        year: i32,
+        key: String,
+        data: RequestData,
+        stamps: Vec<NewSyncState>,
+    },
+    /// A page of teams, written as soon as it arrives.
+    Teams {
+        page: usize,
+        teams: Vec<TeamJSON>,
+        stamps: Vec<NewSyncState>,
+    },
+    /// Every other batch of a season has been sent. The stamps are
+    /// only saved if all of them were written.
+    Season {
+        year: i32,
+        stamps: Vec<NewSyncState>,
+    },
+}
+
+impl Batch {
+    fn year(&self) -> i32 {
+        match *self {
+            Batch::Districts { year, .. } => year,
+            Batch::Event { year, .. } => year,
+            Batch::Season { year, .. } => year,
+            Batch::Teams { .. } => 0,
+        }
+    }
+
+    /// What to call the batch if it could not be written.
+    fn name(&self) -> String {
+        match *self {
+            Batch::Districts { year, .. } => format!("districts/{}", year),
+            Batch::Event { ref key, .. } => key.clone(),
+            Batch::Season { year, .. } => format!("events/{}", year),
+            Batch::Teams { page, .. } => format!("teams/{}", page),
+        }
+    }
+}
+
+/// How much the writer got through.
+pub struct Stats {
+    pub events: usize,
+    pub rows: usize,
+    /// Time spent inside transactions.
+    pub writing: Duration,
+}
+
+/// Switch the database to write-ahead logging, which lets readers
+/// carry on during a sync and makes each commit far cheaper.
+pub fn prepare(conn: &SqliteConnection) -> Result<()> {
+    conn.batch_execute("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+    return Ok(());
+}
+
+/// Start the thread which writes every batch sent to it.
+///
+/// Each batch is written as it arrives, in a transaction of its own, so
+/// an event's rows are committed along with the stamps of the requests
+/// they were read from and nothing is kept of an event which could not be
+/// written. The stamps of a season's list of events are only saved once
+/// every other batch of that season has been written.
+pub fn spawn(conn: SqliteConnection) -> (Sender<Batch>, JoinHandle<(Vec<(String, Error)>, Stats)>) {
+    let (sender, receiver) = channel();
+    let handle = thread::spawn(move || {
+        let mut writer = Writer {
+            conn: conn,
+            failed_years: HashSet::new(),
+            failures: Vec::new(),
+            stats: Stats {
+                events: 0,
+                rows: 0,
+                writing: Duration::from_secs(0),
+            },
+        };
+        for batch in receiver {
+            match batch {
+                Batch::Season { year, stamps } => writer.finish_season(year, stamps),
+                batch => writer.write(batch),
+            }
+        }
+        return (writer.failures, writer.stats);
+    });
+    return (sender, handle);
+}
+
+struct Writer {
+    conn: SqliteConnection,
+    /// Seasons with a batch which could not be written.
+    failed_years: HashSet<i32>,
+    failures: Vec<(String, Error)>,
+    stats: Stats,
+}
+
+impl Writer {
+    /// Write a batch on its own.
+    fn write(&mut self, batch: Batch) {
+        let start = Instant::now();
+        match self.conn.transaction(|| write_batch(&self.conn, &batch)) {
+            Ok(n) => {
+                self.stats.rows += n;
+                if let Batch::Event { .. } = batch {
+                    self.stats.events += 1;
+                }
+            },
+            Err(e) => {
+                self.failed_years.insert(batch.year());
+                self.failures.push((batch.name(), e));
+            },
+        }
+        self.stats.writing += start.elapsed();
+    }
+
+    /// Save the stamps of a season's list of events, unless one of
+    /// its batches failed.
+    fn finish_season(&mut self, year: i32, stamps: Vec<NewSyncState>) {
+        // Keep asking for the full list until every event has been written.
+        if self.failed_years.contains(&year) {
+            return;
+        }
+        self.write(Batch::Season { year: year, stamps: stamps });
+    }
+}
+
+/// Write a single batch, returning the number of rows written.
+fn write_batch(conn: &SqliteConnection, batch: &Batch) -> Result<usize> {
+    use schema::{events, matches, districts, district_teams, rankings, alliance_teams,
+                 awards, teams, event_teams, sync_state};
+    let mut rows = 0;
+    match *batch {
+        Batch::Districts { ref districts, ref members, ref stamps, .. } => {
+            let new_districts: Vec<NewDistrict> = districts.iter()
+                .map(|x| prepare_district(x)).collect();
+            rows += diesel::insert_or_replace(&new_districts)
+                .into(districts::table).execute(conn)?;
+            for &(ref key, ref team_keys) in members {
+                let new_teams: Vec<NewDistrictTeam> = team_keys.iter()
+                    .map(|x| prepare_district_team(key, x)).collect();
+                rows += diesel::insert_or_replace(&new_teams)
+                    .into(district_teams::table).execute(conn)?;
+            }
+            rows += diesel::insert_or_replace(stamps).into(sync_state::table).execute(conn)?;
+        },
+        Batch::Event { ref data, ref stamps, .. } => {
+            let new_events: Vec<NewEvent> = data.events.iter()
+                .map(|x| prepare_event(x)).collect();
+            rows += diesel::insert_or_replace(&new_events)
+                .into(events::table).execute(conn)?;
+            let new_matches = prepare_matches(&data.matches);
+            rows += diesel::insert_or_replace(&new_matches)
+                .into(matches::table).execute(conn)?;
+            rows += diesel::insert_or_replace(&data.rankings)
+                .into(rankings::table).execute(conn)?;
+            rows += diesel::insert_or_replace(&data.alliances)
+                .into(alliance_teams::table).execute(conn)?;
+            rows += diesel::insert_or_replace(&data.awards)
+                .into(awards::table).execute(conn)?;
+            rows += diesel::insert_or_replace(&data.teams)
+                .into(event_teams::table).execute(conn)?;
+            // The stamps are only saved if everything they describe was.
+            rows += diesel::insert_or_replace(stamps).into(sync_state::table).execute(conn)?;
+        },
+        Batch::Teams { ref teams, ref stamps, .. } => {
+            let new_teams: Vec<NewTeam> = teams.iter().map(|x| prepare_team(x)).collect();
+            rows += diesel::insert_or_replace(&new_teams)
+                .into(teams::table).execute(conn)?;
+            rows += diesel::insert_or_replace(stamps).into(sync_state::table).execute(conn)?;
+        },
+        Batch::Season { ref stamps, .. } => {
+            rows += diesel::insert_or_replace(stamps).into(sync_state::table).execute(conn)?;
+        },
+    }
+    return Ok(rows);
+}